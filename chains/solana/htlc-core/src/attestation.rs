@@ -0,0 +1,147 @@
+//! Guardian attestations that the destination leg of a swap completed, which
+//! can settle an HTLC instead of its secret.
+use crate::{Error, Result};
+
+/// Prefix of the attested message, followed by the program id and the Id of
+/// the HTLC.
+pub const DOMAIN: &[u8] = b"HTLC destination leg completed";
+pub const MESSAGE_LEN: usize = DOMAIN.len() + 64;
+
+/// The message attesters sign for the HTLC `id` of `program_id`. Ed25519 keys
+/// sign it as is and secp256k1 keys sign its keccak256.
+pub fn message(program_id: &[u8; 32], id: &[u8; 32]) -> [u8; MESSAGE_LEN] {
+    let mut message = [0u8; MESSAGE_LEN];
+    message[..DOMAIN.len()].copy_from_slice(DOMAIN);
+    message[DOMAIN.len()..DOMAIN.len() + 32].copy_from_slice(program_id);
+    message[DOMAIN.len() + 32..].copy_from_slice(id);
+    message
+}
+
+/// Returns the public keys and messages of the signatures that an Ed25519
+/// program instruction with `data` verifies. Only signatures whose key and
+/// message are stored in the instruction itself are returned.
+pub fn ed25519_signed(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    const OFFSETS_START: usize = 2;
+    const OFFSETS_LEN: usize = 14;
+    const THIS_INSTRUCTION: u16 = u16::MAX;
+    let count = data.first().copied().unwrap_or(0) as usize;
+    let read_u16 = move |at: usize| -> Option<u16> {
+        Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+    };
+    let slice = move |offset: u16, len: usize| data.get(offset as usize..offset as usize + len);
+    (0..count).filter_map(move |i| {
+        let at = OFFSETS_START + i * OFFSETS_LEN;
+        let public_key_offset = read_u16(at + 4)?;
+        let public_key_instruction = read_u16(at + 6)?;
+        let message_offset = read_u16(at + 8)?;
+        let message_len = read_u16(at + 10)?;
+        let message_instruction = read_u16(at + 12)?;
+        if public_key_instruction != THIS_INSTRUCTION || message_instruction != THIS_INSTRUCTION {
+            return None;
+        }
+        Some((
+            slice(public_key_offset, 32)?,
+            slice(message_offset, message_len as usize)?,
+        ))
+    })
+}
+
+/// Fails unless `signed` distinct attesters reach `threshold`, which is 0
+/// while the settlement is disabled.
+pub fn check_quorum(signed: usize, threshold: u8) -> Result<()> {
+    if threshold == 0 {
+        return Err(Error::AttestationDisabled);
+    }
+    if signed < threshold as usize {
+        return Err(Error::AttestationQuorumNotMet);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBLIC_KEY: [u8; 32] = [1u8; 32];
+    const SIGNATURE: [u8; 64] = [2u8; 64];
+    const SIGNED: &[u8] = b"signed message";
+    // Offsets of the first signature: one offsets entry follows the count and
+    // padding bytes, then the public key, the signature and the message.
+    const PUBLIC_KEY_OFFSET: u16 = 16;
+    const SIGNATURE_OFFSET: u16 = 48;
+    const MESSAGE_OFFSET: u16 = 112;
+    const DATA_LEN: usize = MESSAGE_OFFSET as usize + SIGNED.len();
+
+    /// Ed25519 program instruction data with one signature, whose key and
+    /// message are in the instruction `key_instruction` and `message_instruction`.
+    fn ed25519_data(key_instruction: u16, message_instruction: u16) -> [u8; DATA_LEN] {
+        let mut data = [0u8; DATA_LEN];
+        data[0] = 1;
+        let offsets = [
+            SIGNATURE_OFFSET,
+            u16::MAX,
+            PUBLIC_KEY_OFFSET,
+            key_instruction,
+            MESSAGE_OFFSET,
+            SIGNED.len() as u16,
+            message_instruction,
+        ];
+        for (i, offset) in offsets.iter().enumerate() {
+            data[2 + 2 * i..4 + 2 * i].copy_from_slice(&offset.to_le_bytes());
+        }
+        data[16..48].copy_from_slice(&PUBLIC_KEY);
+        data[48..112].copy_from_slice(&SIGNATURE);
+        data[112..].copy_from_slice(SIGNED);
+        data
+    }
+
+    #[test]
+    fn message_is_domain_program_and_id() {
+        let message = message(&[3u8; 32], &[4u8; 32]);
+        assert_eq!(&message[..DOMAIN.len()], DOMAIN);
+        assert_eq!(&message[DOMAIN.len()..DOMAIN.len() + 32], &[3u8; 32]);
+        assert_eq!(&message[DOMAIN.len() + 32..], &[4u8; 32]);
+    }
+
+    #[test]
+    fn reads_signatures_of_the_instruction_itself() {
+        let data = ed25519_data(u16::MAX, u16::MAX);
+        let mut signed = ed25519_signed(&data);
+        assert_eq!(signed.next(), Some((&PUBLIC_KEY[..], SIGNED)));
+        assert_eq!(signed.next(), None);
+    }
+
+    #[test]
+    fn skips_keys_and_messages_of_other_instructions() {
+        assert_eq!(ed25519_signed(&ed25519_data(0, u16::MAX)).count(), 0);
+        assert_eq!(ed25519_signed(&ed25519_data(u16::MAX, 1)).count(), 0);
+    }
+
+    #[test]
+    fn skips_malformed_offsets() {
+        // Offsets past the end of the data.
+        let mut data = ed25519_data(u16::MAX, u16::MAX);
+        data[6..8].copy_from_slice(&(DATA_LEN as u16 - 31).to_le_bytes());
+        assert_eq!(ed25519_signed(&data).count(), 0);
+        let mut data = ed25519_data(u16::MAX, u16::MAX);
+        data[10..12].copy_from_slice(&u16::MAX.to_le_bytes());
+        data[12..14].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert_eq!(ed25519_signed(&data).count(), 0);
+        // A count of more signatures than there are offsets.
+        let mut data = ed25519_data(u16::MAX, u16::MAX);
+        data[0] = 200;
+        assert_eq!(ed25519_signed(&data).count(), 1);
+        // Data too short for the offsets of its count.
+        assert_eq!(ed25519_signed(&[1, 0, 0, 0]).count(), 0);
+        assert_eq!(ed25519_signed(&[]).count(), 0);
+    }
+
+    #[test]
+    fn counts_the_quorum() {
+        assert_eq!(check_quorum(3, 0), Err(Error::AttestationDisabled));
+        assert_eq!(check_quorum(0, 0), Err(Error::AttestationDisabled));
+        assert_eq!(check_quorum(1, 2), Err(Error::AttestationQuorumNotMet));
+        assert_eq!(check_quorum(2, 2), Ok(()));
+        assert_eq!(check_quorum(3, 2), Ok(()));
+    }
+}
@@ -0,0 +1,26 @@
+use core::fmt;
+
+/// Errors of the shared rules. Each one has a variant of the same name and
+/// error code in the `HTLCError` of both programs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    NotFutureTimeLock,
+    NotPastTimeLock,
+    HashlockNotSet,
+    HashlockNoMatch,
+    HashlockAlreadySet,
+    AlreadyRedeemed,
+    AlreadyRefunded,
+    InvalidCommitDeadline,
+    CommitDeadlinePassed,
+    AttestationDisabled,
+    AttestationQuorumNotMet,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{Error, Result};
+use sha2::{Digest, Sha256};
+
+/// The hashlock of `secret`, its sha256.
+pub fn hash_secret(secret: &[u8; 32]) -> [u8; 32] {
+    Sha256::digest(secret).into()
+}
+
+/// Fails unless `hashlock` is set and is the hash of `secret`.
+pub fn verify_secret(hashlock: &[u8; 32], secret: &[u8; 32]) -> Result<()> {
+    if *hashlock == [0u8; 32] {
+        return Err(Error::HashlockNotSet);
+    }
+    if hash_secret(secret) != *hashlock {
+        return Err(Error::HashlockNoMatch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sha256 of 32 zero bytes.
+    const ZERO_SECRET_HASH: [u8; 32] = [
+        0x66, 0x68, 0x7a, 0xad, 0xf8, 0x62, 0xbd, 0x77, 0x6c, 0x8f, 0xc1, 0x8b, 0x8e, 0x9f, 0x8e,
+        0x20, 0x08, 0x97, 0x14, 0x85, 0x6e, 0xe2, 0x33, 0xb3, 0x90, 0x2a, 0x59, 0x1d, 0x0d, 0x5f,
+        0x29, 0x25,
+    ];
+
+    #[test]
+    fn hashes_with_sha256() {
+        assert_eq!(hash_secret(&[0u8; 32]), ZERO_SECRET_HASH);
+    }
+
+    #[test]
+    fn verifies_secret() {
+        assert_eq!(verify_secret(&ZERO_SECRET_HASH, &[0u8; 32]), Ok(()));
+        assert_eq!(
+            verify_secret(&ZERO_SECRET_HASH, &[1u8; 32]),
+            Err(Error::HashlockNoMatch)
+        );
+        // A commit without a hashlock can't be redeemed, even by a secret
+        // whose hash would be zero.
+        assert_eq!(
+            verify_secret(&[0u8; 32], &[0u8; 32]),
+            Err(Error::HashlockNotSet)
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use sha2::{Digest, Sha256};
+
+/// The Id a sender can use for a commit, the sha256 of the program, the sender,
+/// the receiver, the amount and the timelock. It only depends on its arguments,
+/// so clients can derive it off chain.
+pub fn commit_id(
+    program_id: &[u8; 32],
+    sender: &[u8; 32],
+    receiver: &[u8; 32],
+    amount: u64,
+    timelock: u64,
+) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(program_id)
+        .chain_update(sender)
+        .chain_update(receiver)
+        .chain_update(amount.to_be_bytes())
+        .chain_update(timelock.to_be_bytes())
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_the_fields_in_order() {
+        let (program_id, sender, receiver) = ([1u8; 32], [2u8; 32], [3u8; 32]);
+        let mut preimage = [0u8; 112];
+        preimage[..32].copy_from_slice(&program_id);
+        preimage[32..64].copy_from_slice(&sender);
+        preimage[64..96].copy_from_slice(&receiver);
+        preimage[96..104].copy_from_slice(&1000u64.to_be_bytes());
+        preimage[104..].copy_from_slice(&1_700_000_000u64.to_be_bytes());
+        let expected: [u8; 32] = Sha256::digest(preimage).into();
+        assert_eq!(
+            commit_id(&program_id, &sender, &receiver, 1000, 1_700_000_000),
+            expected
+        );
+    }
+
+    #[test]
+    fn depends_on_every_field() {
+        let id = commit_id(&[1u8; 32], &[2u8; 32], &[3u8; 32], 1000, 60);
+        assert_ne!(id, commit_id(&[9u8; 32], &[2u8; 32], &[3u8; 32], 1000, 60));
+        assert_ne!(id, commit_id(&[1u8; 32], &[3u8; 32], &[2u8; 32], 1000, 60));
+        assert_ne!(id, commit_id(&[1u8; 32], &[2u8; 32], &[3u8; 32], 1001, 60));
+        assert_ne!(id, commit_id(&[1u8; 32], &[2u8; 32], &[3u8; 32], 1000, 61));
+    }
+}
@@ -0,0 +1,22 @@
+//! Rules shared by `native_htlc` (`sol/`) and `anchor_htlc` (`token/`): the
+//! hashlock, the derivation of commit Ids, the state transitions of an HTLC,
+//! guardian attestations and the validation of routes and destination
+//! addresses.
+//!
+//! The crate has no Solana or Anchor dependencies and doesn't allocate, so it
+//! builds for any target. Times are plain `u64`s, either UNIX seconds or slots
+//! depending on the timelock kind of the HTLC, and the programs map `Error`
+//! onto their own `HTLCError`.
+#![no_std]
+
+pub mod attestation;
+pub mod error;
+pub mod hashlock;
+pub mod id;
+pub mod route;
+pub mod state;
+
+pub use error::{Error, Result};
+pub use hashlock::{hash_secret, verify_secret};
+pub use id::commit_id;
+pub use state::{check_new, State};
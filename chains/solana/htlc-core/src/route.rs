@@ -0,0 +1,518 @@
+//! Validation of the CAIP ids of the registry and of destination addresses.
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+
+/// Seed of a registry entry, the sha256 of its id, which can be longer than a seed.
+pub fn registry_key(id: &str) -> [u8; 32] {
+    Sha256::digest(id.as_bytes()).into()
+}
+
+fn is_caip_namespace(namespace: &str) -> bool {
+    (3..=8).contains(&namespace.len())
+        && namespace
+            .bytes()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == b'-')
+}
+
+/// Whether `id` is a CAIP-2 chain id, `namespace:reference`.
+pub fn is_caip2(id: &str) -> bool {
+    match id.split_once(':') {
+        Some((namespace, reference)) => {
+            is_caip_namespace(namespace)
+                && (1..=32).contains(&reference.len())
+                && reference
+                    .bytes()
+                    .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+        }
+        None => false,
+    }
+}
+
+/// Returns the chain id of a CAIP-19 asset id,
+/// `chain_id/asset_namespace:asset_reference`, or None if `id` is not one.
+pub fn caip19_chain(id: &str) -> Option<&str> {
+    let (chain_id, asset) = id.split_once('/')?;
+    let (namespace, reference) = asset.split_once(':')?;
+    let valid = is_caip2(chain_id)
+        && is_caip_namespace(namespace)
+        && (1..=128).contains(&reference.len())
+        && reference
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || b"-.%".contains(&c));
+    valid.then_some(chain_id)
+}
+
+/// Returns the 1 to `max` hex digits of a 0x prefixed hex string.
+fn hex_digits(address: &str, max: usize) -> Option<&str> {
+    let digits = address.strip_prefix("0x")?;
+    ((1..=max).contains(&digits.len()) && digits.bytes().all(|c| c.is_ascii_hexdigit()))
+        .then_some(digits)
+}
+
+/// Whether `address` is 0x and 40 hex digits, with the EIP-55 checksum if it
+/// is mixed case.
+pub fn is_evm_address(address: &str) -> bool {
+    let Some(digits) = hex_digits(address, 40).filter(|digits| digits.len() == 40) else {
+        return false;
+    };
+    if !digits.bytes().any(|c| c.is_ascii_uppercase())
+        || !digits.bytes().any(|c| c.is_ascii_lowercase())
+    {
+        return true;
+    }
+    // EIP-55: a letter is upper case if its nibble of the keccak hash of the
+    // lower case address is 8 or more.
+    let mut lower = [0u8; 40];
+    for (l, c) in lower.iter_mut().zip(digits.bytes()) {
+        *l = c.to_ascii_lowercase();
+    }
+    let hash = Keccak256::digest(lower);
+    digits.bytes().enumerate().all(|(i, c)| {
+        let nibble = if i % 2 == 0 {
+            hash[i / 2] >> 4
+        } else {
+            hash[i / 2] & 0xf
+        };
+        !c.is_ascii_alphabetic() || c.is_ascii_uppercase() == (nibble >= 8)
+    })
+}
+
+/// A Bitcoin network, which determines the prefixes of its addresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitcoinNetwork {
+    Mainnet,
+    /// Testnet3, testnet4 and signet, which share their address prefixes.
+    Testnet,
+    Regtest,
+}
+
+impl BitcoinNetwork {
+    /// The network of a `bip122` CAIP-2 chain id, whose reference is the
+    /// start of the genesis block hash, or None for any other chain.
+    pub fn from_chain_id(chain_id: &str) -> Option<Self> {
+        match chain_id {
+            "bip122:000000000019d6689c085ae165831e93" => Some(Self::Mainnet),
+            "bip122:000000000933ea01ad0ee984209779ba"
+            | "bip122:00000000da84f2bafbbc53dee25a72ae"
+            | "bip122:00000008819873e925422c1ff0f99f7c" => Some(Self::Testnet),
+            "bip122:0f9188f13cb7b2c71f2a335e3a4fc328" => Some(Self::Regtest),
+            _ => None,
+        }
+    }
+
+    /// Human readable part of the segwit addresses of the network.
+    fn hrp(self) -> &'static [u8] {
+        match self {
+            Self::Mainnet => b"bc",
+            Self::Testnet => b"tb",
+            Self::Regtest => b"bcrt",
+        }
+    }
+
+    /// Version bytes of the P2PKH and P2SH addresses of the network.
+    fn base58_versions(self) -> [u8; 2] {
+        match self {
+            Self::Mainnet => [0x00, 0x05],
+            Self::Testnet | Self::Regtest => [0x6f, 0xc4],
+        }
+    }
+}
+
+/// Whether `address` is a Bitcoin address of `network`: segwit bech32 or
+/// bech32m, or base58check P2PKH or P2SH.
+pub fn is_bitcoin_address(network: BitcoinNetwork, address: &str) -> bool {
+    is_bech32_address(network.hrp(), address)
+        || is_base58check_address(network.base58_versions(), address)
+}
+
+/// Whether `address` is 0x and up to 64 hex digits of a Starknet felt below 2^251.
+pub fn is_starknet_address(address: &str) -> bool {
+    hex_digits(address, 64).is_some_and(|digits| {
+        let digits = digits.trim_start_matches('0');
+        digits.len() < 63 || (digits.len() == 63 && digits.as_bytes()[0] <= b'7')
+    })
+}
+
+/// Whether `address` is 0x and 64 hex digits, as Sui and Fuel addresses are.
+pub fn is_hex32_address(address: &str) -> bool {
+    hex_digits(address, 64).is_some_and(|digits| digits.len() == 64)
+}
+
+/// Whether `address` is the base58 of a 32 byte Solana public key.
+pub fn is_solana_address(address: &str) -> bool {
+    let mut bytes = [0u8; 32];
+    bs58::decode(address).onto(&mut bytes) == Ok(32)
+}
+
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn bech32_value(c: u8) -> Option<u8> {
+    BECH32_CHARSET
+        .iter()
+        .position(|&b| b == c.to_ascii_lowercase())
+        .map(|i| i as u8)
+}
+
+fn bech32_polymod(values: impl Iterator<Item = u8>) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    values.fold(1, |checksum, value| {
+        let top = checksum >> 25;
+        let checksum = (checksum & 0x1ffffff) << 5 ^ value as u32;
+        (0..5)
+            .filter(|i| (top >> i) & 1 == 1)
+            .fold(checksum, |checksum, i| checksum ^ GENERATOR[i])
+    })
+}
+
+/// Whether `address` is a segwit address, bech32 for version 0 and bech32m
+/// for later versions (BIP-173 and BIP-350), with the human readable part
+/// `expected_hrp`.
+fn is_bech32_address(expected_hrp: &[u8], address: &str) -> bool {
+    let bytes = address.as_bytes();
+    if bytes.len() > 90
+        || (bytes.iter().any(u8::is_ascii_lowercase) && bytes.iter().any(u8::is_ascii_uppercase))
+    {
+        return false;
+    }
+    let Some(separator) = bytes.iter().rposition(|&c| c == b'1') else {
+        return false;
+    };
+    let (hrp, data) = (&bytes[..separator], &bytes[separator + 1..]);
+    if !hrp.eq_ignore_ascii_case(expected_hrp)
+        || data.len() < 7
+        || !data.iter().all(|&c| bech32_value(c).is_some())
+    {
+        return false;
+    }
+    let values = data.iter().map(|&c| bech32_value(c).unwrap_or(0));
+    let version = bech32_value(data[0]).unwrap_or(0);
+    let constant = if version == 0 { 1 } else { 0x2bc830a3 };
+    let hrp_lower = hrp.iter().map(u8::to_ascii_lowercase);
+    let hrp_expanded = hrp_lower
+        .clone()
+        .map(|c| c >> 5)
+        .chain([0])
+        .chain(hrp_lower.map(|c| c & 31));
+    if version > 16 || bech32_polymod(hrp_expanded.chain(values.clone())) != constant {
+        return false;
+    }
+    // The witness program in 5 bit groups, without the version and checksum.
+    let program = values.skip(1).take(data.len() - 7);
+    let (padding, bits) = program.fold((0u32, 0usize), |(acc, bits), value| {
+        (((acc << 5) | value as u32) & 0xff, bits + 5)
+    });
+    let program_len = bits / 8;
+    let padding_bits = bits % 8;
+    padding_bits < 5
+        && padding & ((1 << padding_bits) - 1) == 0
+        && (2..=40).contains(&program_len)
+        && (version != 0 || program_len == 20 || program_len == 32)
+}
+
+/// Whether `address` is a P2PKH or P2SH address with one of the version
+/// bytes `versions`.
+fn is_base58check_address(versions: [u8; 2], address: &str) -> bool {
+    let mut bytes = [0u8; 25];
+    if bs58::decode(address).onto(&mut bytes) != Ok(25) || !versions.contains(&bytes[0]) {
+        return false;
+    }
+    let checksum = Sha256::digest(Sha256::digest(&bytes[..21]));
+    checksum[..4] == bytes[21..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAINNET: &str = "bip122:000000000019d6689c085ae165831e93";
+    const TESTNET: &str = "bip122:000000000933ea01ad0ee984209779ba";
+    const REGTEST: &str = "bip122:0f9188f13cb7b2c71f2a335e3a4fc328";
+
+    #[test]
+    fn parses_caip2_ids() {
+        // Examples of the CAIP-2 specification.
+        for id in [
+            "eip155:1",
+            MAINNET,
+            "cosmos:cosmoshub-3",
+            "cosmos:Binance-Chain-Tigris",
+            "starknet:SN_GOERLI",
+            "chainstd:8c3444cf8970a9e41a706fab93e7a6c4",
+        ] {
+            assert!(is_caip2(id), "{id}");
+        }
+        for id in [
+            "eip155",
+            "eip155:",
+            "ei:1",
+            "namespace9:1",
+            "EIP155:1",
+            "eip155:1:2",
+            "eip155:000000000019d6689c085ae165831e931",
+        ] {
+            assert!(!is_caip2(id), "{id}");
+        }
+    }
+
+    #[test]
+    fn parses_caip19_ids() {
+        // Examples of the CAIP-19 specification.
+        assert_eq!(caip19_chain("eip155:1/slip44:60"), Some("eip155:1"));
+        assert_eq!(
+            caip19_chain("bip122:000000000019d6689c085ae165831e93/slip44:0"),
+            Some(MAINNET)
+        );
+        assert_eq!(
+            caip19_chain("eip155:1/erc20:0x6b175474e89094c44da98b954eedeac495271d0f"),
+            Some("eip155:1")
+        );
+        assert_eq!(
+            caip19_chain("cosmos:cosmoshub-3/slip44:118"),
+            Some("cosmos:cosmoshub-3")
+        );
+        for id in [
+            "eip155:1",
+            "eip155:1/slip44",
+            "eip155:1/slip44:",
+            "eip155/slip44:60",
+            "eip155:1/SLIP44:60",
+            "eip155:1/slip44:6 0",
+        ] {
+            assert_eq!(caip19_chain(id), None, "{id}");
+        }
+    }
+
+    #[test]
+    fn checks_eip55_checksums() {
+        // Examples of EIP-55.
+        for address in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+            "0x52908400098527886E0F7030069857D2E4169EE7",
+            "0xde709f2102306220921060314715629080e2fb77",
+        ] {
+            assert!(is_evm_address(address), "{address}");
+        }
+        for address in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD",
+            "0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA",
+            "5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed00",
+            "0xgaAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        ] {
+            assert!(!is_evm_address(address), "{address}");
+        }
+    }
+
+    #[test]
+    fn selects_bitcoin_networks_by_chain_id() {
+        assert_eq!(
+            BitcoinNetwork::from_chain_id(MAINNET),
+            Some(BitcoinNetwork::Mainnet)
+        );
+        assert_eq!(
+            BitcoinNetwork::from_chain_id(TESTNET),
+            Some(BitcoinNetwork::Testnet)
+        );
+        assert_eq!(
+            BitcoinNetwork::from_chain_id(REGTEST),
+            Some(BitcoinNetwork::Regtest)
+        );
+        assert_eq!(BitcoinNetwork::from_chain_id("eip155:1"), None);
+        assert_eq!(
+            BitcoinNetwork::from_chain_id("bip122:12a765e31ffd4059bada1e25190f6e98"),
+            None
+        );
+    }
+
+    #[test]
+    fn checks_segwit_addresses() {
+        use BitcoinNetwork::*;
+        // Valid addresses of BIP-173 and BIP-350.
+        for (network, address) in [
+            (Mainnet, "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4"),
+            (
+                Testnet,
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+            ),
+            (
+                Mainnet,
+                "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y",
+            ),
+            (Mainnet, "BC1SW50QGDZ25J"),
+            (Mainnet, "bc1zw508d6qejxtdg4y5r3zarvaryvaxxpcs"),
+            (
+                Testnet,
+                "tb1qqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesrxh6hy",
+            ),
+            (
+                Testnet,
+                "tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c",
+            ),
+            (
+                Mainnet,
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+            ),
+        ] {
+            assert!(is_bitcoin_address(network, address), "{address}");
+        }
+        // Invalid addresses of BIP-350.
+        for (network, address) in [
+            // Unknown human readable part.
+            (
+                Testnet,
+                "tc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq5zuyut",
+            ),
+            // Bech32 instead of bech32m checksum.
+            (
+                Mainnet,
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd",
+            ),
+            (
+                Testnet,
+                "tb1z0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqglt7rf",
+            ),
+            (
+                Mainnet,
+                "BC1S0XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ54WELL",
+            ),
+            // Bech32m instead of bech32 checksum.
+            (Mainnet, "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh"),
+            (
+                Testnet,
+                "tb1q0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq24jc47",
+            ),
+            // Invalid character.
+            (
+                Mainnet,
+                "bc1p38j9r5y49hruaue7wxjce0updqjuyyx0kh56v8s25huc6995vvpql3jow4",
+            ),
+            // Invalid witness version.
+            (
+                Mainnet,
+                "BC130XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ7ZWS8R",
+            ),
+            // Invalid program lengths.
+            (Mainnet, "bc1pw5dgrnzv"),
+            (
+                Mainnet,
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7v8n0nx0muaewav253zgeav",
+            ),
+            (Mainnet, "BC1QR508D6QEJXTDG4Y5R3ZARVARYV98GJ9P"),
+            // Mixed case.
+            (
+                Testnet,
+                "tb1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq47Zagq",
+            ),
+            // Padding of more than 4 bits, and non-zero padding.
+            (
+                Mainnet,
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7v07qwwzcrf",
+            ),
+            (
+                Testnet,
+                "tb1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vpggkg4j",
+            ),
+            // Empty data.
+            (Mainnet, "bc1gmk9yu"),
+        ] {
+            assert!(!is_bitcoin_address(network, address), "{address}");
+        }
+    }
+
+    #[test]
+    fn checks_base58check_addresses() {
+        use BitcoinNetwork::*;
+        // P2PKH of the genesis block and P2SH examples.
+        assert!(is_bitcoin_address(
+            Mainnet,
+            "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"
+        ));
+        assert!(is_bitcoin_address(
+            Mainnet,
+            "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy"
+        ));
+        assert!(is_bitcoin_address(
+            Testnet,
+            "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn"
+        ));
+        assert!(is_bitcoin_address(
+            Testnet,
+            "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc"
+        ));
+        assert!(is_bitcoin_address(
+            Regtest,
+            "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn"
+        ));
+        // Bad checksum.
+        assert!(!is_bitcoin_address(
+            Mainnet,
+            "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb"
+        ));
+    }
+
+    #[test]
+    fn rejects_addresses_of_other_bitcoin_networks() {
+        use BitcoinNetwork::*;
+        assert!(!is_bitcoin_address(
+            Testnet,
+            "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"
+        ));
+        assert!(!is_bitcoin_address(
+            Mainnet,
+            "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn"
+        ));
+        assert!(!is_bitcoin_address(
+            Testnet,
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0"
+        ));
+        assert!(!is_bitcoin_address(
+            Mainnet,
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7"
+        ));
+        assert!(!is_bitcoin_address(
+            Regtest,
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7"
+        ));
+    }
+
+    #[test]
+    fn checks_starknet_felts() {
+        for address in [
+            "0x0",
+            "0x021b6a2ff227f1c71cc6536e7b9e8ecd0d5599b3a934279011e2f2b923d3a782",
+            // 2^251 - 1, with and without a leading zero.
+            "0x7ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            "0x07ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        ] {
+            assert!(is_starknet_address(address), "{address}");
+        }
+        for address in [
+            // 2^251.
+            "0x800000000000000000000000000000000000000000000000000000000000000",
+            "0x0800000000000000000000000000000000000000000000000000000000000000",
+            "0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            // 65 digits.
+            "0x00000000000000000000000000000000000000000000000000000000000000001",
+            "0x",
+            "021b6a2ff227f1c71cc6536e7b9e8ecd0d5599b3a934279011e2f2b923d3a782",
+        ] {
+            assert!(!is_starknet_address(address), "{address}");
+        }
+    }
+
+    #[test]
+    fn checks_hex32_and_solana_addresses() {
+        let hex32 = "0x021b6a2ff227f1c71cc6536e7b9e8ecd0d5599b3a934279011e2f2b923d3a782";
+        assert!(is_hex32_address(hex32));
+        assert!(!is_hex32_address(&hex32[..65]));
+        assert!(is_solana_address("11111111111111111111111111111111"));
+        assert!(is_solana_address(
+            "So11111111111111111111111111111111111111112"
+        ));
+        assert!(!is_solana_address("1111"));
+        assert!(!is_solana_address("0OIl111111111111111111111111111111"));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{verify_secret, Error, Result};
+
+/// Fails unless a new HTLC with `timelock`, and optionally `commit_deadline`,
+/// can be created at `now`.
+pub fn check_new(now: u64, timelock: u64, commit_deadline: Option<u64>) -> Result<()> {
+    if timelock <= now {
+        return Err(Error::NotFutureTimeLock);
+    }
+    if let Some(commit_deadline) = commit_deadline {
+        if commit_deadline <= now || commit_deadline >= timelock {
+            return Err(Error::InvalidCommitDeadline);
+        }
+    }
+    Ok(())
+}
+
+/// The parts of an HTLC its state transitions depend on. `timelock`,
+/// `commit_deadline` and the `now` passed to the methods are all times or all
+/// slots.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct State {
+    /// Zero while the HTLC is a commit without a hashlock.
+    pub hashlock: [u8; 32],
+    pub timelock: u64,
+    /// Zero if the commit has no deadline.
+    pub commit_deadline: u64,
+    pub redeemed: bool,
+    pub refunded: bool,
+}
+
+impl State {
+    /// Fails if the HTLC was already redeemed or refunded.
+    pub fn check_open(&self) -> Result<()> {
+        if self.redeemed {
+            return Err(Error::AlreadyRedeemed);
+        }
+        if self.refunded {
+            return Err(Error::AlreadyRefunded);
+        }
+        Ok(())
+    }
+
+    /// Whether the timelock has passed.
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.timelock
+    }
+
+    /// Whether the HTLC is still without a hashlock after its commit deadline.
+    pub fn is_past_commit_deadline(&self, now: u64) -> bool {
+        self.hashlock == [0u8; 32] && self.commit_deadline != 0 && now >= self.commit_deadline
+    }
+
+    /// Whether the HTLC can be refunded, after the timelock or early after the
+    /// commit deadline.
+    pub fn is_refundable(&self, now: u64) -> bool {
+        self.is_expired(now) || self.is_past_commit_deadline(now)
+    }
+
+    /// Fails unless the hashlock can be added to the commit with the new
+    /// `timelock` at `now`.
+    pub fn check_add_lock(&self, now: u64, timelock: u64) -> Result<()> {
+        self.check_open()?;
+        if self.hashlock != [0u8; 32] {
+            return Err(Error::HashlockAlreadySet);
+        }
+        if timelock <= now {
+            return Err(Error::NotFutureTimeLock);
+        }
+        if self.is_past_commit_deadline(now) {
+            return Err(Error::CommitDeadlinePassed);
+        }
+        Ok(())
+    }
+
+    /// Fails unless the HTLC can be redeemed with `secret`.
+    pub fn check_redeem(&self, secret: &[u8; 32]) -> Result<()> {
+        self.check_open()?;
+        verify_secret(&self.hashlock, secret)
+    }
+
+    /// Fails unless the HTLC can be refunded at `now`.
+    pub fn check_refund(&self, now: u64) -> Result<()> {
+        self.check_open()?;
+        if !self.is_refundable(now) {
+            return Err(Error::NotPastTimeLock);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_secret;
+
+    const SECRET: [u8; 32] = [7u8; 32];
+
+    fn locked(timelock: u64) -> State {
+        State {
+            hashlock: hash_secret(&SECRET),
+            timelock,
+            ..State::default()
+        }
+    }
+
+    fn committed(timelock: u64, commit_deadline: u64) -> State {
+        State {
+            timelock,
+            commit_deadline,
+            ..State::default()
+        }
+    }
+
+    #[test]
+    fn new_htlcs_need_a_future_timelock() {
+        assert_eq!(check_new(100, 100, None), Err(Error::NotFutureTimeLock));
+        assert_eq!(check_new(100, 99, None), Err(Error::NotFutureTimeLock));
+        assert_eq!(check_new(100, 101, None), Ok(()));
+    }
+
+    #[test]
+    fn commit_deadlines_lie_between_now_and_the_timelock() {
+        assert_eq!(
+            check_new(100, 200, Some(100)),
+            Err(Error::InvalidCommitDeadline)
+        );
+        assert_eq!(
+            check_new(100, 200, Some(200)),
+            Err(Error::InvalidCommitDeadline)
+        );
+        assert_eq!(check_new(100, 200, Some(101)), Ok(()));
+        assert_eq!(check_new(100, 200, Some(199)), Ok(()));
+    }
+
+    #[test]
+    fn redeems_open_htlcs_with_the_secret() {
+        let state = locked(200);
+        assert_eq!(state.check_redeem(&SECRET), Ok(()));
+        assert_eq!(state.check_redeem(&[0u8; 32]), Err(Error::HashlockNoMatch));
+        assert_eq!(
+            committed(200, 0).check_redeem(&SECRET),
+            Err(Error::HashlockNotSet)
+        );
+        let redeemed = State {
+            redeemed: true,
+            ..state
+        };
+        assert_eq!(redeemed.check_redeem(&SECRET), Err(Error::AlreadyRedeemed));
+        let refunded = State {
+            refunded: true,
+            ..state
+        };
+        assert_eq!(refunded.check_redeem(&SECRET), Err(Error::AlreadyRefunded));
+    }
+
+    #[test]
+    fn refunds_from_the_timelock_on() {
+        let state = locked(200);
+        assert_eq!(state.check_refund(199), Err(Error::NotPastTimeLock));
+        assert_eq!(state.check_refund(200), Ok(()));
+        assert_eq!(state.check_refund(201), Ok(()));
+        let redeemed = State {
+            redeemed: true,
+            ..state
+        };
+        assert_eq!(redeemed.check_refund(200), Err(Error::AlreadyRedeemed));
+        let refunded = State {
+            refunded: true,
+            ..state
+        };
+        assert_eq!(refunded.check_refund(200), Err(Error::AlreadyRefunded));
+    }
+
+    #[test]
+    fn refunds_commits_early_from_the_commit_deadline_on() {
+        let state = committed(200, 150);
+        assert!(!state.is_past_commit_deadline(149));
+        assert_eq!(state.check_refund(149), Err(Error::NotPastTimeLock));
+        assert!(state.is_past_commit_deadline(150));
+        assert_eq!(state.check_refund(150), Ok(()));
+        // The deadline no longer applies once the hashlock is added.
+        let locked = State {
+            hashlock: hash_secret(&SECRET),
+            ..state
+        };
+        assert!(!locked.is_past_commit_deadline(150));
+        assert_eq!(locked.check_refund(150), Err(Error::NotPastTimeLock));
+        // Without a deadline only the timelock counts.
+        assert_eq!(
+            committed(200, 0).check_refund(150),
+            Err(Error::NotPastTimeLock)
+        );
+    }
+
+    #[test]
+    fn adds_the_hashlock_before_the_commit_deadline() {
+        let state = committed(200, 150);
+        assert_eq!(state.check_add_lock(149, 300), Ok(()));
+        assert_eq!(
+            state.check_add_lock(150, 300),
+            Err(Error::CommitDeadlinePassed)
+        );
+        assert_eq!(
+            state.check_add_lock(149, 149),
+            Err(Error::NotFutureTimeLock)
+        );
+        assert_eq!(
+            locked(200).check_add_lock(100, 300),
+            Err(Error::HashlockAlreadySet)
+        );
+        let refunded = State {
+            refunded: true,
+            ..state
+        };
+        assert_eq!(
+            refunded.check_add_lock(100, 300),
+            Err(Error::AlreadyRefunded)
+        );
+    }
+}
@@ -1,6 +1,16 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::secp256k1_recover::secp256k1_recover;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::solana_program::sysvar::instructions::load_instruction_at_checked;
 use anchor_lang::system_program;
-use sha2::{Digest, Sha256};
+use anchor_lang::Discriminator;
+use bytemuck::Zeroable;
+pub use htlc_core::route::registry_key;
+use htlc_core::route::{self, caip19_chain, is_caip2, BitcoinNetwork};
 use std::mem::size_of;
 declare_id!("2XfmTmnhz8kDnryZSJKKV53tLN7DKZbrN9Q1sZbJo5bc");
 /// @title Pre Hashed Timelock Contracts (PHTLCs) on Solana.
@@ -19,9 +29,51 @@ declare_id!("2XfmTmnhz8kDnryZSJKKV53tLN7DKZbrN9Q1sZbJo5bc");
 ///      to add the hashlock to HTLC.
 ///  4) redeem(Id, secret) - once the src_receiver knows the secret of
 ///      the hashlock hash they can claim the sol with this function
-///  5) refund(Id) - after timelock has expired and if the src_receiver did not
+///  5) refund(Id) - after timelock has expired (or the commit deadline passed
+///      without a hashlock) and if the src_receiver did not
 ///      redeem the sol the sender / creator of the HTLC can get their sol
 ///      back with this function.
+///  6) top_up(Id, amount) - the sender calls this function to add sol
+///      to a committed HTLC before the hashlock is set.
+///  7) redeem_by_hashlock(hashlock, secret) - same as redeem, for callers
+///      that only know the sender and the hashlock. The Id is resolved through
+///      the hashlock index of the sender.
+///  8) migrate(Id) - anyone can call this function to rewrite an HTLC
+///      stored in an older account layout into the current one.
+///  9) add_lock_sig(Id, hashlock, timelock, signature) - anyone can call this
+///      function to add the hashlock with a signature of the EVM key the
+///      sender recorded at commit.
+/// 10) initialize_config(admin) / set_admin(admin) - the upgrade authority sets
+///      the admin of the program, the admin can hand it over.
+/// 11) set_limits(max_open_htlcs, max_locked) - the admin caps the HTLCs a
+///      sender can have open and the lamports it can have locked at once.
+/// 12) init_compressed_tree(max_depth, max_buffer_size) - anyone can call this
+///      to set up a concurrent Merkle tree for compressed HTLCs.
+/// 13) lock_compressed(src_receiver, hashlock, timelock, amount) - a sender
+///      calls this to lock sol in a leaf of a compressed tree instead of an
+///      HTLC account. The leaf index is returned.
+/// 14) redeem_compressed(htlc, index, root, secret) / refund_compressed(htlc,
+///      index, root) - same as redeem and refund, for a compressed HTLC. The
+///      leaf is proven with the proof passed as remaining accounts.
+/// 15) link_counterparty(Id, counterparty_id, lock_tx_hash) - the src_receiver
+///      records the destination chain HTLC of the swap and its lock transaction.
+/// 16) set_attesters(attesters, threshold) / redeem_attested(Id, signatures) -
+///      the admin sets m-of-n attesters, whose attestation that the destination
+///      leg completed redeems an HTLC instead of its secret.
+///
+/// The open HTLCs and the locked lamports of every sender are tracked in a
+/// sender stats PDA, redeem and refund release them.
+///
+/// lock, add_lock and lock_compressed record every hashlock in a hashlock index PDA of the
+/// sender, so a sender can only ever use a hashlock (and thereby its secret)
+/// for one HTLC. The index is per sender, so nobody else can take a hashlock
+/// before its sender uses it.
+///
+/// The sender can be a PDA signing through CPI. The payer covers the rent of the
+/// new accounts, so the sender doesn't need to be able to create accounts. Senders
+/// that carry data can't be the source of a system transfer, so their program
+/// credits the lamports to the HTLC account before calling commit, lock or
+/// top_up, see is_prefunded.
 #[program]
 pub mod native_htlc {
     use super::*;
@@ -32,26 +84,28 @@ pub mod native_htlc {
         amount: u64,
         timelock: u64,
     ) -> Result<[u8; 32]> {
-        let sender = &ctx.accounts.sender.to_account_info().key;
-        let receiver = &ctx.accounts.receiver.to_account_info().key;
-
-        let mut hasher = Sha256::new();
-        hasher.update(ctx.program_id);
-        hasher.update(sender);
-        hasher.update(receiver);
-        hasher.update(&amount.to_be_bytes());
-        hasher.update(&timelock.to_be_bytes());
-
-        let commitId = hasher.finalize();
-
-        Ok(commitId.into())
+        Ok(htlc_core::commit_id(
+            &ctx.program_id.to_bytes(),
+            &ctx.accounts.sender.key().to_bytes(),
+            &ctx.accounts.receiver.key().to_bytes(),
+            amount,
+            timelock,
+        ))
     }
 
     /// @dev Sender / Payer sets up a new pre-hash time lock contract depositing the
     /// funds and providing the src_receiver and terms.
     /// @param src_receiver src_receiver of the funds.
-    /// @param timelock UNIX epoch seconds time that the lock expires at.
+    /// @param timelock UNIX epoch seconds or slot that the lock expires at.
     ///                  Refunds can be made after this time.
+    /// @param timelock_kind whether the timelock is in UNIX epoch seconds or slots.
+    /// @param evm_signer optional EVM key that can authorize add_lock_sig.
+    /// @param refund_to optional address refunds are paid to instead of the sender.
+    /// @param commit_deadline optional time or slot, in the clock of timelock_kind,
+    ///                        before the timelock. If no hashlock is set by then
+    ///                        the sender can refund early.
+    /// @param metadata opaque bytes for the sender, e.g. order and quote ids,
+    ///                 at most MAX_METADATA_LEN.
     /// @return Id of the new HTLC. This is needed for subsequent calls.
     pub fn commit(
         ctx: Context<Commit>,
@@ -65,47 +119,78 @@ pub mod native_htlc {
         src_asset: String,
         src_receiver: Pubkey,
         timelock: u64,
+        timelock_kind: TimelockKind,
         amount: u64,
+        evm_signer: Option<EvmSigner>,
+        refund_to: Option<Pubkey>,
+        commit_deadline: Option<u64>,
+        metadata: Vec<u8>,
         commit_bump: u8,
     ) -> Result<[u8; 32]> {
         let clock = Clock::get().unwrap();
-        require!(
-            timelock > clock.unix_timestamp.try_into().unwrap(),
-            HTLCError::NotFutureTimeLock
-        );
+        htlc_core::check_new(timelock_kind.now(&clock), timelock, commit_deadline)
+            .map_err(HTLCError::from)?;
         require!(amount != 0, HTLCError::FundsNotSent);
-        let htlc = &mut ctx.accounts.htlc;
+        check_route(
+            &ctx.accounts.dst_chain_entry,
+            &ctx.accounts.dst_asset_entry,
+            &ctx.accounts.src_asset_entry,
+            &dst_chain,
+            &dst_asset,
+            &dst_address,
+            &src_asset,
+        )?;
+        ctx.accounts.sender_stats.sender = ctx.accounts.sender.key();
+        ctx.accounts.sender_stats.open(
+            amount,
+            ctx.accounts.config.max_open_htlcs,
+            ctx.accounts.config.max_locked,
+        )?;
 
-        htlc.dst_address = dst_address;
-        htlc.dst_chain = dst_chain;
-        htlc.dst_asset = dst_asset;
-        htlc.src_asset = src_asset;
+        if !is_prefunded(&ctx.accounts.htlc.to_account_info(), 0, amount)? {
+            let bump_vector = commit_bump.to_le_bytes();
+            let inner = vec![Id.as_ref(), bump_vector.as_ref()];
+            let outer = vec![inner.as_slice()];
+
+            let transfer_context = CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.sender.to_account_info(),
+                    to: ctx.accounts.htlc.to_account_info(),
+                },
+                outer.as_slice(),
+            );
+            system_program::transfer(transfer_context, amount)?;
+        }
+
+        let mut htlc = ctx.accounts.htlc.load_init()?;
+        htlc.version = HTLC_VERSION;
+        htlc.counted = 1;
+        htlc.set_route(&dst_chain, &dst_asset, &dst_address, &src_asset)?;
+        (htlc.metadata, htlc.metadata_len) = pack_bytes(&metadata)?;
         htlc.sender = *ctx.accounts.sender.to_account_info().key;
+        htlc.refund_to = refund_to.unwrap_or(htlc.sender);
         htlc.src_receiver = src_receiver;
         htlc.hashlock = [0u8; 32];
         htlc.amount = amount;
         htlc.timelock = timelock;
-        htlc.redeemed = false;
-        htlc.refunded = false;
+        htlc.timelock_kind = timelock_kind as u8;
         htlc.secret = [0u8; 32];
-
-        let bump_vector = commit_bump.to_le_bytes();
-        let inner = vec![Id.as_ref(), bump_vector.as_ref()];
-        let outer = vec![inner.as_slice()];
-
-        let transfer_context = CpiContext::new_with_signer(
-            ctx.accounts.system_program.to_account_info(),
-            system_program::Transfer {
-                from: ctx.accounts.sender.to_account_info(),
-                to: htlc.to_account_info(),
-            },
-            outer.as_slice(),
-        );
-        system_program::transfer(transfer_context, amount)?;
+        htlc.evm_signer = evm_signer.map_or([0u8; 20], |signer| signer.address());
+        htlc.commit_deadline = commit_deadline.unwrap_or(0);
         // msg!("hop chains: {:?}", hopChains);
         // msg!("hop assets: {:?}", hopAssets);
         // msg!("hop addresses: {:?}", hopAddresses);
 
+        emit!(Committed {
+            Id,
+            sender: htlc.sender,
+            src_receiver,
+            amount,
+            timelock,
+            metadata,
+        });
+
         Ok(Id)
     }
 
@@ -113,61 +198,98 @@ pub mod native_htlc {
     /// funds and providing the reciever and terms.
     /// @param src_receiver receiver of the funds.
     /// @param hashlock A sha-256 hash hashlock.
-    /// @param timelock UNIX epoch seconds time that the lock expires at.
+    /// @param timelock UNIX epoch seconds or slot that the lock expires at.
     ///                  Refunds can be made after this time.
+    /// @param timelock_kind whether the timelock is in UNIX epoch seconds or slots.
+    /// @param refund_to optional address refunds are paid to instead of the sender.
+    /// @param splits optional receivers that get a share of the funds on redeem,
+    ///               see Split.
+    /// @param metadata opaque bytes for the sender, e.g. order and quote ids,
+    ///                 at most MAX_METADATA_LEN.
     /// @return Id of the new HTLC. This is needed for subsequent calls.
     pub fn lock(
         ctx: Context<Lock>,
         Id: [u8; 32],
         hashlock: [u8; 32],
         timelock: u64,
+        timelock_kind: TimelockKind,
         amount: u64,
         dst_chain: String,
         dst_address: String,
         dst_asset: String,
         src_asset: String,
         src_receiver: Pubkey,
+        refund_to: Option<Pubkey>,
+        splits: Vec<Split>,
+        metadata: Vec<u8>,
         lock_bump: u8,
     ) -> Result<[u8; 32]> {
         let clock = Clock::get().unwrap();
-        require!(
-            timelock > clock.unix_timestamp.try_into().unwrap(),
-            HTLCError::NotFutureTimeLock
-        );
+        htlc_core::check_new(timelock_kind.now(&clock), timelock, None).map_err(HTLCError::from)?;
         require!(amount != 0, HTLCError::FundsNotSent);
+        check_route(
+            &ctx.accounts.dst_chain_entry,
+            &ctx.accounts.dst_asset_entry,
+            &ctx.accounts.src_asset_entry,
+            &dst_chain,
+            &dst_asset,
+            &dst_address,
+            &src_asset,
+        )?;
+        ctx.accounts.sender_stats.sender = ctx.accounts.sender.key();
+        ctx.accounts.sender_stats.open(
+            amount,
+            ctx.accounts.config.max_open_htlcs,
+            ctx.accounts.config.max_locked,
+        )?;
 
-        let htlc = &mut ctx.accounts.htlc;
+        if !is_prefunded(&ctx.accounts.htlc.to_account_info(), 0, amount)? {
+            let bump_vector = lock_bump.to_le_bytes();
+            let inner = vec![Id.as_ref(), bump_vector.as_ref()];
+            let outer = vec![inner.as_slice()];
+            let transfer_context = CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.sender.to_account_info(),
+                    to: ctx.accounts.htlc.to_account_info(),
+                },
+                outer.as_slice(),
+            );
+            system_program::transfer(transfer_context, amount)?;
+        }
 
-        htlc.dst_address = dst_address;
-        htlc.dst_chain = dst_chain;
-        htlc.dst_asset = dst_asset;
-        htlc.src_asset = src_asset;
+        let mut htlc = ctx.accounts.htlc.load_init()?;
+        htlc.version = HTLC_VERSION;
+        htlc.counted = 1;
+        htlc.set_route(&dst_chain, &dst_asset, &dst_address, &src_asset)?;
+        (htlc.metadata, htlc.metadata_len) = pack_bytes(&metadata)?;
         htlc.sender = *ctx.accounts.sender.to_account_info().key;
+        htlc.refund_to = refund_to.unwrap_or(htlc.sender);
         htlc.src_receiver = src_receiver;
         htlc.hashlock = hashlock;
+        htlc.set_splits(&splits)?;
         htlc.secret = [0u8; 32];
         htlc.amount = amount;
         htlc.timelock = timelock;
-        htlc.redeemed = false;
-        htlc.refunded = false;
+        htlc.timelock_kind = timelock_kind as u8;
+        ctx.accounts.hashlock_index.id = Id;
 
-        let bump_vector = lock_bump.to_le_bytes();
-        let inner = vec![Id.as_ref(), bump_vector.as_ref()];
-        let outer = vec![inner.as_slice()];
-        let transfer_context = CpiContext::new_with_signer(
-            ctx.accounts.system_program.to_account_info(),
-            system_program::Transfer {
-                from: ctx.accounts.sender.to_account_info(),
-                to: htlc.to_account_info(),
-            },
-            outer.as_slice(),
-        );
-        system_program::transfer(transfer_context, amount)?;
+        emit!(Locked {
+            Id,
+            hashlock,
+            sender: htlc.sender,
+            src_receiver,
+            amount,
+            timelock,
+            metadata,
+        });
 
         Ok(Id)
     }
 
     /// @dev Called by the sender to add hashlock to the HTLC
+    /// @param splits optional receivers that get a share of the funds on redeem,
+    ///               see Split.
     ///
     /// @param Id of the HTLC to addLock.
     /// @param hashlock of the HTLC to be locked.
@@ -176,16 +298,98 @@ pub mod native_htlc {
         Id: [u8; 32],
         hashlock: [u8; 32],
         timelock: u64,
+        splits: Vec<Split>,
+    ) -> Result<[u8; 32]> {
+        let clock = Clock::get().unwrap();
+        let mut htlc = ctx.accounts.htlc.load_mut()?;
+        htlc.state()
+            .check_add_lock(htlc.timelock_kind().now(&clock), timelock)
+            .map_err(HTLCError::from)?;
+
+        htlc.hashlock = hashlock;
+        htlc.timelock = timelock;
+        htlc.set_splits(&splits)?;
+        ctx.accounts.hashlock_index.id = Id;
+
+        Ok(Id)
+    }
+
+    /// @dev Called by anyone to add the hashlock to the HTLC, authorized by a
+    /// signature of the EVM key the sender recorded at commit. The signed message
+    /// is addLockMsg(bytes32 Id,bytes32 hashlock,uint256 timelock), see add_lock_digest.
+    ///
+    /// @param Id of the HTLC to addLock.
+    /// @param hashlock of the HTLC to be locked.
+    /// @param message_kind whether the message was signed as EIP-712 typed data
+    ///                      or as an EIP-191 personal message.
+    /// @param signature r and s of the secp256k1 signature.
+    /// @param recovery_id v of the secp256k1 signature, 0/1 or 27/28.
+    pub fn add_lock_sig(
+        ctx: Context<AddLockSig>,
+        Id: [u8; 32],
+        hashlock: [u8; 32],
+        timelock: u64,
+        message_kind: EvmMessageKind,
+        signature: [u8; 64],
+        recovery_id: u8,
     ) -> Result<[u8; 32]> {
         let clock = Clock::get().unwrap();
+        let mut htlc = ctx.accounts.htlc.load_mut()?;
+        htlc.state()
+            .check_add_lock(htlc.timelock_kind().now(&clock), timelock)
+            .map_err(HTLCError::from)?;
+        let digest = add_lock_digest(message_kind, &Id, &hashlock, timelock);
         require!(
-            timelock > clock.unix_timestamp.try_into().unwrap(),
-            HTLCError::NotFutureTimeLock
+            recover_evm_address(&digest, &signature, recovery_id)? == htlc.evm_signer,
+            HTLCError::InvalidSignature
         );
 
-        let htlc = &mut ctx.accounts.htlc;
         htlc.hashlock = hashlock;
         htlc.timelock = timelock;
+        ctx.accounts.hashlock_index.id = Id;
+
+        Ok(Id)
+    }
+
+    /// @dev Called by the sender to add funds to a committed HTLC
+    /// whose hashlock is not set yet.
+    ///
+    /// @param Id of the HTLC to top up.
+    /// @param amount of lamports to be added.
+    pub fn top_up(ctx: Context<TopUp>, Id: [u8; 32], amount: u64) -> Result<[u8; 32]> {
+        require!(amount != 0, HTLCError::FundsNotSent);
+        let locked = ctx.accounts.htlc.load()?.amount;
+        let total = locked
+            .checked_add(amount)
+            .ok_or(HTLCError::AmountOverflow)?;
+        if ctx.accounts.htlc.load()?.counted != 0 {
+            let max_locked = ctx.accounts.config.max_locked;
+            ctx.accounts
+                .sender_stats
+                .as_mut()
+                .ok_or(HTLCError::SenderStatsRequired)?
+                .add(amount, max_locked)?;
+        }
+
+        if !is_prefunded(&ctx.accounts.htlc.to_account_info(), locked, amount)? {
+            let transfer_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.sender.to_account_info(),
+                    to: ctx.accounts.htlc.to_account_info(),
+                },
+            );
+            system_program::transfer(transfer_context, amount)?;
+        }
+
+        let mut htlc = ctx.accounts.htlc.load_mut()?;
+        htlc.amount = total;
+
+        emit!(ToppedUp {
+            Id,
+            amount,
+            total: htlc.amount,
+        });
 
         Ok(Id)
     }
@@ -196,60 +400,508 @@ pub mod native_htlc {
     /// @param Id of the HTLC.
     /// @param secret sha256(secret) should equal the contract hashlock.
     pub fn redeem(ctx: Context<Redeem>, Id: [u8; 32], secret: [u8; 32]) -> Result<bool> {
-        let htlc = &mut ctx.accounts.htlc;
-        let mut hasher = Sha256::new();
-        hasher.update(secret);
-        let hash = hasher.finalize();
-        require!([0u8; 32] != htlc.hashlock, HTLCError::HashlockNotSet);
-        require!(hash == htlc.hashlock.into(), HTLCError::HashlockNoMatch);
-
-        htlc.redeemed = true;
-        htlc.secret = secret;
-
-        let amount = htlc.amount;
-
-        htlc.sub_lamports(amount)?;
-        ctx.accounts.src_receiver.add_lamports(amount)?;
+        ctx.accounts
+            .htlc
+            .load()?
+            .state()
+            .check_redeem(&secret)
+            .map_err(HTLCError::from)?;
+        pay_redeem(
+            &ctx.accounts.htlc,
+            &ctx.accounts.src_receiver,
+            &mut ctx.accounts.sender_stats,
+            ctx.remaining_accounts,
+            secret,
+        )?;
+        Ok(true)
+    }
 
+    /// @dev Called by anyone to redeem an HTLC without its secret, with an
+    /// attestation of the attesters in the config that the destination leg of
+    /// the swap completed. Ed25519 attesters sign through Ed25519 program
+    /// instructions in the same transaction, secp256k1 attesters through
+    /// `signatures`. The signed message is htlc_core::attestation::message.
+    ///
+    /// @param Id of the HTLC.
+    /// @param signatures of the secp256k1 attesters.
+    pub fn redeem_attested(
+        ctx: Context<Redeem>,
+        Id: [u8; 32],
+        signatures: Vec<AttesterSignature>,
+    ) -> Result<bool> {
+        ctx.accounts
+            .htlc
+            .load()?
+            .state()
+            .check_open()
+            .map_err(HTLCError::from)?;
+        let config = ctx
+            .accounts
+            .config
+            .as_ref()
+            .ok_or(HTLCError::AttestationDisabled)?;
+        let signed = config.attested(&Id, &signatures, ctx.accounts.instructions.as_deref())?;
+        htlc_core::attestation::check_quorum(signed, config.attester_threshold)
+            .map_err(HTLCError::from)?;
+        pay_redeem(
+            &ctx.accounts.htlc,
+            &ctx.accounts.src_receiver,
+            &mut ctx.accounts.sender_stats,
+            ctx.remaining_accounts,
+            [0u8; 32],
+        )?;
         Ok(true)
     }
 
+    /// @dev Called by the src_receiver once they know the secret of the hashlock,
+    /// when the Id of the HTLC is not known. The HTLC is resolved through the
+    /// hashlock index of its sender.
+    ///
+    /// @param _hashlock of the HTLC, which derives its hashlock index.
+    /// @param secret sha256(secret) should equal the hashlock.
+    pub fn redeem_by_hashlock(
+        ctx: Context<RedeemByHashlock>,
+        _hashlock: [u8; 32],
+        secret: [u8; 32],
+    ) -> Result<[u8; 32]> {
+        ctx.accounts
+            .htlc
+            .load()?
+            .state()
+            .check_redeem(&secret)
+            .map_err(HTLCError::from)?;
+        pay_redeem(
+            &ctx.accounts.htlc,
+            &ctx.accounts.src_receiver,
+            &mut ctx.accounts.sender_stats,
+            ctx.remaining_accounts,
+            secret,
+        )?;
+        Ok(ctx.accounts.hashlock_index.id)
+    }
+
     /// @dev Called by the sender if there was no redeem AND the time lock has
-    /// expired. This will refund the contract amount.
+    /// expired, or no hashlock was set by the commit deadline. This will refund
+    /// the contract amount to the HTLC's refund_to address.
     ///
     /// @param Id of the HTLC to refund from.
     pub fn refund(ctx: Context<Refund>, Id: [u8; 32]) -> Result<bool> {
-        let htlc = &mut ctx.accounts.htlc;
+        let mut htlc = ctx.accounts.htlc.load_mut()?;
 
-        htlc.refunded = true;
+        htlc.refunded = 1;
 
         let amount = htlc.amount;
+        release_sender_stats(&mut ctx.accounts.sender_stats, &htlc)?;
+        drop(htlc);
 
-        htlc.sub_lamports(amount)?;
-        ctx.accounts.sender.add_lamports(amount)?;
+        ctx.accounts.htlc.sub_lamports(amount)?;
+        ctx.accounts.refund_to.add_lamports(amount)?;
 
         Ok(true)
     }
 
-    /// @dev Get HTLC details.
+    /// @dev Called by the src_receiver (the solver) after locking on the
+    /// destination chain, to record the HTLC there and its lock transaction.
+    /// An HTLC can only be linked once.
+    ///
     /// @param Id of the HTLC.
-    pub fn getDetails(ctx: Context<GetDetails>, Id: [u8; 32]) -> Result<HTLC> {
-        let htlc = &ctx.accounts.htlc;
-        msg!("hashlcok: {:?}", htlc.hashlock);
-        Ok(HTLC {
-            dst_address: htlc.dst_address.clone(),
-            dst_chain: htlc.dst_chain.clone(),
-            dst_asset: htlc.dst_asset.clone(),
-            src_asset: htlc.src_asset.clone(),
-            sender: htlc.sender,
-            src_receiver: htlc.src_receiver,
-            hashlock: htlc.hashlock,
-            secret: htlc.secret.clone(),
-            amount: htlc.amount,
-            timelock: htlc.timelock,
-            redeemed: htlc.redeemed,
-            refunded: htlc.refunded,
-        })
+    /// @param counterparty_id Id of the destination chain HTLC.
+    /// @param lock_tx_hash hash or signature of the destination lock transaction.
+    pub fn link_counterparty(
+        ctx: Context<LinkCounterparty>,
+        Id: [u8; 32],
+        counterparty_id: [u8; 32],
+        lock_tx_hash: Vec<u8>,
+    ) -> Result<()> {
+        require!(
+            counterparty_id != [0u8; 32] && !lock_tx_hash.is_empty(),
+            HTLCError::InvalidCounterparty
+        );
+        let counterparty = &mut ctx.accounts.counterparty;
+        counterparty.Id = Id;
+        counterparty.counterparty_id = counterparty_id;
+        (counterparty.lock_tx_hash, counterparty.lock_tx_hash_len) = pack_bytes(&lock_tx_hash)?;
+
+        emit!(CounterpartyLinked {
+            Id,
+            counterparty_id,
+            lock_tx_hash,
+        });
+        Ok(())
+    }
+
+    /// @dev Rewrites an HTLC stored in an older account layout into the
+    /// current one, reallocating the account if it needs more space.
+    /// Anyone can call this, the payer covers the rent of the extra space.
+    ///
+    /// @param Id of the HTLC to migrate.
+    /// @return the layout version the HTLC was stored in.
+    pub fn migrate(ctx: Context<Migrate>, Id: [u8; 32]) -> Result<u8> {
+        let htlc_info = ctx.accounts.htlc.to_account_info();
+        let (version, legacy) = HTLC::stored_layout(&htlc_info.try_borrow_data()?)?;
+        let Some(legacy) = legacy else {
+            return err!(HTLCError::AlreadyMigrated);
+        };
+
+        let old_len = htlc_info.data_len();
+        let new_len = old_len.max(HTLC::SPACE);
+        if new_len > old_len {
+            let rent = Rent::get()?;
+            let transfer_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: htlc_info.clone(),
+                },
+            );
+            system_program::transfer(
+                transfer_context,
+                rent.minimum_balance(new_len) - rent.minimum_balance(old_len),
+            )?;
+            htlc_info.realloc(new_len, true)?;
+        }
+        htlc_info.try_borrow_mut_data()?[8..].fill(0);
+
+        let mut htlc = ctx.accounts.htlc.load_mut()?;
+        htlc.set_from_legacy(legacy);
+        htlc.version = HTLC_VERSION;
+
+        Ok(version)
+    }
+
+    /// @dev Get HTLC details. HTLCs in an older layout are read without
+    /// migrating them, `version` reports their stored layout.
+    /// @param Id of the HTLC.
+    /// @return the HTLCDetails of the HTLC.
+    pub fn getDetails(ctx: Context<GetDetails>, Id: [u8; 32]) -> Result<HTLCDetails> {
+        let htlc_info = ctx.accounts.htlc.to_account_info();
+        let data = htlc_info.try_borrow_data()?;
+        Ok(HTLC::read_any_version(&data)?.details())
+    }
+
+    /// @dev Called once by the upgrade authority of the program to set its admin.
+    /// @param admin that sets the limits.
+    pub fn initialize_config(ctx: Context<InitializeConfig>, admin: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = admin;
+        config.bump = ctx.bumps.config;
+        Ok(())
+    }
+
+    /// @dev Called by the admin to hand the admin role over.
+    /// @param admin the new admin.
+    pub fn set_admin(ctx: Context<SetAdmin>, admin: Pubkey) -> Result<()> {
+        ctx.accounts.config.admin = admin;
+        Ok(())
+    }
+
+    /// @dev Called by the admin to cap what a sender can have open at once.
+    /// HTLCs that are already open are not affected.
+    /// @param max_open_htlcs most open HTLCs per sender, 0 for no limit.
+    /// @param max_locked most lamports locked per sender, 0 for no limit.
+    pub fn set_limits(ctx: Context<SetLimits>, max_open_htlcs: u32, max_locked: u64) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.max_open_htlcs = max_open_htlcs;
+        config.max_locked = max_locked;
+        Ok(())
+    }
+
+    /// @dev Called by the admin to set the attesters of the guardian
+    /// settlement, see redeem_attested.
+    /// @param attesters up to MAX_ATTESTERS distinct keys.
+    /// @param threshold attestations needed to redeem, 0 disables the settlement.
+    pub fn set_attesters(
+        ctx: Context<SetAttesters>,
+        attesters: Vec<Attester>,
+        threshold: u8,
+    ) -> Result<()> {
+        require!(
+            attesters.len() <= MAX_ATTESTERS
+                && threshold as usize <= attesters.len()
+                && attesters.iter().all(Attester::is_valid)
+                && (1..attesters.len()).all(|i| !attesters[..i].contains(&attesters[i])),
+            HTLCError::InvalidAttesters
+        );
+        let config = &mut ctx.accounts.config;
+        config.attesters = Default::default();
+        config.attesters[..attesters.len()].copy_from_slice(&attesters);
+        config.attester_len = attesters.len() as u8;
+        config.attester_threshold = threshold;
+        emit!(AttestersSet {
+            attesters,
+            threshold
+        });
+        Ok(())
+    }
+
+    /// @dev Sets up a concurrent Merkle tree of compressed HTLCs. The tree
+    /// account has to be created beforehand, owned by spl-account-compression
+    /// and sized for max_depth, max_buffer_size and its canopy. The tree is
+    /// owned by the CompressedTree PDA of this program.
+    /// @param max_depth of the tree, it holds up to 2^max_depth HTLCs.
+    /// @param max_buffer_size number of changes a proof can lag behind.
+    pub fn init_compressed_tree(
+        ctx: Context<InitCompressedTree>,
+        max_depth: u32,
+        max_buffer_size: u32,
+    ) -> Result<()> {
+        let compressed_tree = &mut ctx.accounts.compressed_tree;
+        compressed_tree.merkle_tree = ctx.accounts.merkle_tree.key();
+        compressed_tree.bump = ctx.bumps.compressed_tree;
+
+        compression_cpi(
+            &ctx.accounts.compressed(),
+            &[],
+            account_compression::init_empty_merkle_tree(max_depth, max_buffer_size),
+        )
+    }
+
+    /// @dev Called by the sender to lock sol in a compressed HTLC. The HTLC is
+    /// appended as a leaf to the tree, so no HTLC account is created. Its
+    /// hashlock is recorded in the hashlock index of the sender like that of
+    /// any other HTLC. The sol is held by the CompressedTree PDA.
+    /// The leaf and its index are emitted in CompressedLocked, they are needed
+    /// to redeem or refund it.
+    /// @param Id of the HTLC, part of the leaf.
+    /// @param hashlock of the HTLC to be locked.
+    /// @param timelock of the HTLC, in the clock given by timelock_kind.
+    /// @param amount of lamports to lock.
+    /// @param src_receiver that can redeem the funds.
+    /// @param refund_to optional address refunds are paid to, the sender if none.
+    /// @param metadata opaque bytes for the sender, at most MAX_METADATA_LEN.
+    /// @return index of the leaf of the new HTLC.
+    pub fn lock_compressed(
+        ctx: Context<LockCompressed>,
+        Id: [u8; 32],
+        hashlock: [u8; 32],
+        timelock: u64,
+        timelock_kind: TimelockKind,
+        amount: u64,
+        dst_chain: String,
+        dst_address: String,
+        dst_asset: String,
+        src_asset: String,
+        src_receiver: Pubkey,
+        refund_to: Option<Pubkey>,
+        metadata: Vec<u8>,
+    ) -> Result<u32> {
+        let clock = Clock::get().unwrap();
+        htlc_core::check_new(timelock_kind.now(&clock), timelock, None).map_err(HTLCError::from)?;
+        require!(amount != 0, HTLCError::FundsNotSent);
+        check_route(
+            &ctx.accounts.dst_chain_entry,
+            &ctx.accounts.dst_asset_entry,
+            &ctx.accounts.src_asset_entry,
+            &dst_chain,
+            &dst_asset,
+            &dst_address,
+            &src_asset,
+        )?;
+        pack_str::<MAX_ADDRESS_LEN>(&dst_address)?;
+        pack_bytes::<MAX_METADATA_LEN>(&metadata)?;
+        ctx.accounts.sender_stats.sender = ctx.accounts.sender.key();
+        ctx.accounts.sender_stats.open(
+            amount,
+            ctx.accounts.config.max_open_htlcs,
+            ctx.accounts.config.max_locked,
+        )?;
+
+        let transfer_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.sender.to_account_info(),
+                to: ctx.accounts.compressed_tree.to_account_info(),
+            },
+        );
+        system_program::transfer(transfer_context, amount)?;
+
+        let sender = ctx.accounts.sender.key();
+        let htlc = CompressedHtlc {
+            Id,
+            sender,
+            src_receiver,
+            refund_to: refund_to.unwrap_or(sender),
+            hashlock,
+            secret: [0u8; 32],
+            amount,
+            timelock,
+            timelock_kind,
+            redeemed: false,
+            refunded: false,
+        };
+        let index = ctx.accounts.compressed_tree.next_index;
+        compression_cpi(
+            &ctx.accounts.compressed(),
+            &[],
+            account_compression::append(htlc.leaf()?),
+        )?;
+        ctx.accounts.compressed_tree.next_index += 1;
+        ctx.accounts.hashlock_index.id = Id;
+
+        emit!(CompressedLocked {
+            merkle_tree: ctx.accounts.merkle_tree.key(),
+            index,
+            htlc,
+            dst_chain,
+            dst_address,
+            dst_asset,
+            src_asset,
+            metadata,
+        });
+
+        Ok(index)
+    }
+
+    /// @dev Called by the src_receiver of a compressed HTLC once they know the
+    /// secret of the hashlock. The proof of the leaf is passed as remaining
+    /// accounts.
+    /// @param htlc the current contents of the leaf.
+    /// @param index of the leaf.
+    /// @param root of the tree the proof is for.
+    /// @param secret sha256(secret) should equal the hashlock.
+    pub fn redeem_compressed<'info>(
+        ctx: Context<'_, '_, '_, 'info, RedeemCompressed<'info>>,
+        htlc: CompressedHtlc,
+        index: u32,
+        root: [u8; 32],
+        secret: [u8; 32],
+    ) -> Result<bool> {
+        htlc.state()
+            .check_redeem(&secret)
+            .map_err(HTLCError::from)?;
+        require!(
+            htlc.src_receiver == ctx.accounts.src_receiver.key(),
+            HTLCError::NotReciever
+        );
+
+        let mut redeemed = htlc.clone();
+        redeemed.redeemed = true;
+        redeemed.secret = secret;
+        replace_compressed(
+            &ctx.accounts.compressed(),
+            ctx.remaining_accounts,
+            &htlc,
+            &redeemed,
+            index,
+            root,
+        )?;
+        ctx.accounts.sender_stats.release(htlc.amount);
+
+        ctx.accounts.compressed_tree.sub_lamports(htlc.amount)?;
+        ctx.accounts.src_receiver.add_lamports(htlc.amount)?;
+
+        emit!(CompressedUpdated {
+            merkle_tree: ctx.accounts.merkle_tree.key(),
+            index,
+            htlc: redeemed,
+        });
+
+        Ok(true)
+    }
+
+    /// @dev Called by the sender of a compressed HTLC if there was no redeem
+    /// and the timelock has expired. The proof of the leaf is passed as
+    /// remaining accounts.
+    /// @param htlc the current contents of the leaf.
+    /// @param index of the leaf.
+    /// @param root of the tree the proof is for.
+    pub fn refund_compressed<'info>(
+        ctx: Context<'_, '_, '_, 'info, RefundCompressed<'info>>,
+        htlc: CompressedHtlc,
+        index: u32,
+        root: [u8; 32],
+    ) -> Result<bool> {
+        htlc.state()
+            .check_refund(htlc.timelock_kind.now(&Clock::get().unwrap()))
+            .map_err(HTLCError::from)?;
+        require!(
+            htlc.refund_to == ctx.accounts.refund_to.key(),
+            HTLCError::NotRefundTo
+        );
+
+        let mut refunded = htlc.clone();
+        refunded.refunded = true;
+        replace_compressed(
+            &ctx.accounts.compressed(),
+            ctx.remaining_accounts,
+            &htlc,
+            &refunded,
+            index,
+            root,
+        )?;
+        ctx.accounts.sender_stats.release(htlc.amount);
+
+        ctx.accounts.compressed_tree.sub_lamports(htlc.amount)?;
+        ctx.accounts.refund_to.add_lamports(htlc.amount)?;
+
+        emit!(CompressedUpdated {
+            merkle_tree: ctx.accounts.merkle_tree.key(),
+            index,
+            htlc: refunded,
+        });
+
+        Ok(true)
+    }
+
+    /// @dev Called by the admin to add a destination chain to the registry, or to
+    /// enable or disable it.
+    /// @param chain_id CAIP-2 id of the chain, e.g. "eip155:1".
+    /// @param family kind of the chain, which determines the format of its addresses.
+    /// @param enabled whether new HTLCs can use the chain.
+    pub fn set_chain(
+        ctx: Context<SetChain>,
+        chain_id: String,
+        family: ChainFamily,
+        enabled: bool,
+    ) -> Result<()> {
+        require!(
+            is_caip2(&chain_id) && family.is_valid_chain(&chain_id),
+            HTLCError::InvalidCaipId
+        );
+        let chain_entry = &mut ctx.accounts.chain_entry;
+        (chain_entry.chain_id, chain_entry.chain_id_len) = pack_str(&chain_id)?;
+        chain_entry.family = family;
+        chain_entry.enabled = enabled;
+        emit!(ChainSet {
+            chain_id,
+            family,
+            enabled,
+        });
+        Ok(())
+    }
+
+    /// @dev Called by the admin to add an asset to the registry, or to enable or
+    /// disable it. The chain of the asset has to be registered.
+    /// @param asset_id CAIP-19 id of the asset, e.g. "eip155:1/slip44:60".
+    /// @param enabled whether new HTLCs can use the asset.
+    pub fn set_asset(ctx: Context<SetAsset>, asset_id: String, enabled: bool) -> Result<()> {
+        let chain_id = caip19_chain(&asset_id).ok_or(HTLCError::InvalidCaipId)?;
+        require!(
+            ctx.accounts.chain_entry.chain_id() == chain_id.as_bytes(),
+            HTLCError::ChainNotRegistered
+        );
+        let asset_entry = &mut ctx.accounts.asset_entry;
+        (asset_entry.asset_id, asset_entry.asset_id_len) = pack_str(&asset_id)?;
+        asset_entry.enabled = enabled;
+        emit!(AssetSet { asset_id, enabled });
+        Ok(())
+    }
+
+    /// @dev Get the state of many HTLCs at once, for example from a simulated
+    /// transaction. The HTLC accounts are passed as remaining accounts, accounts
+    /// that are not an HTLC of this program are reported as missing.
+    /// Up to MAX_BATCH_LEN summaries fit in the return data.
+    /// @return a summary of every HTLC, in the order of the remaining accounts.
+    pub fn get_details_batch(ctx: Context<GetDetailsBatch>) -> Result<Vec<HTLCSummary>> {
+        require!(
+            ctx.remaining_accounts.len() <= MAX_BATCH_LEN,
+            HTLCError::BatchTooLarge
+        );
+        Ok(ctx
+            .remaining_accounts
+            .iter()
+            .map(|account| HTLC::summary_of(account, &ctx.accounts.clock))
+            .collect())
     }
 }
 
@@ -259,127 +911,1252 @@ pub struct IdStruct {
     pub id: [u8; 32],
 }
 
+/// Program settings, at the PDA of `b"config"`.
 #[account]
 #[derive(Default)]
-pub struct HTLC {
-    pub dst_address: String,
-    pub dst_chain: String,
-    pub dst_asset: String,
-    pub src_asset: String,
-    pub sender: Pubkey,
-    pub src_receiver: Pubkey,
-    pub hashlock: [u8; 32],
-    pub secret: [u8; 32],
-    pub amount: u64,
-    pub timelock: u64,
-    pub redeemed: bool,
-    pub refunded: bool,
-}
-#[derive(Accounts)]
-pub struct GetCommitId<'info> {
-    ///CHECK: The sender
-    pub sender: UncheckedAccount<'info>,
-    ///CHECK: The reciever
-    pub receiver: UncheckedAccount<'info>,
+pub struct Config {
+    /// Sets the limits.
+    pub admin: Pubkey,
+    pub bump: u8,
+    /// Most HTLCs a sender can have open at once, 0 for no limit.
+    pub max_open_htlcs: u32,
+    /// Most lamports a sender can have locked at once, 0 for no limit.
+    pub max_locked: u64,
+    /// Attesters of the guardian settlement, `attester_len` of them are used.
+    pub attesters: [Attester; MAX_ATTESTERS],
+    pub attester_len: u8,
+    /// Attestations redeem_attested needs, 0 while the settlement is disabled.
+    pub attester_threshold: u8,
 }
 
-#[derive(Accounts)]
-#[instruction(Id: [u8; 32], commit_bump: u8)]
-pub struct Commit<'info> {
-    #[account(mut)]
-    pub sender: Signer<'info>,
+impl Config {
+    /// Counts the distinct attesters that attested the completion of the
+    /// destination leg of the HTLC `id`, through `signatures` or through the
+    /// Ed25519 program instructions of the transaction.
+    pub fn attested(
+        &self,
+        id: &[u8; 32],
+        signatures: &[AttesterSignature],
+        instructions: Option<&AccountInfo>,
+    ) -> Result<usize> {
+        let message = htlc_core::attestation::message(&crate::ID.to_bytes(), id);
+        let attesters = &self.attesters[..self.attester_len as usize];
+        let mut signed = [false; MAX_ATTESTERS];
 
-    #[account(
-        init,
-        payer = sender,
-        space = size_of::<HTLC>() + 28,
-        seeds = [
-            Id.as_ref()
-        ],
-        bump,
-    )]
-    pub htlc: Box<Account<'info, HTLC>>,
+        let digest = keccak::hash(&message).0;
+        for signature in signatures {
+            let index = signature.attester as usize;
+            let attester = attesters
+                .get(index)
+                .filter(|attester| attester.kind == AttesterKind::Secp256k1)
+                .ok_or(HTLCError::InvalidSignature)?;
+            let signer = recover_evm_address(&digest, &signature.signature, signature.recovery_id)?;
+            require!(attester.key[..20] == signer, HTLCError::InvalidSignature);
+            signed[index] = true;
+        }
 
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+        if let Some(instructions) = instructions {
+            let mut index = 0;
+            while let Ok(instruction) = load_instruction_at_checked(index, instructions) {
+                if instruction.program_id == ed25519_program::ID {
+                    for (public_key, signed_message) in
+                        htlc_core::attestation::ed25519_signed(&instruction.data)
+                    {
+                        let attester = attesters.iter().position(|attester| {
+                            attester.kind == AttesterKind::Ed25519 && attester.key == public_key
+                        });
+                        if let (Some(attester), true) = (attester, signed_message == message) {
+                            signed[attester] = true;
+                        }
+                    }
+                }
+                index += 1;
+            }
+        }
+        Ok(signed.iter().filter(|signed| **signed).count())
+    }
 }
 
-#[derive(Accounts)]
-#[instruction(Id: [u8; 32], lock_bump: u8)]
-pub struct Lock<'info> {
-    #[account(mut)]
-    pub sender: Signer<'info>,
+/// Kind of key of an Attester.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum AttesterKind {
+    #[default]
+    Ed25519,
+    Secp256k1,
+}
 
-    #[account(
-        init,
-        payer = sender,
-        space = size_of::<HTLC>() + 28,
-        seeds = [
-            Id.as_ref()
-        ],
-        bump,
-    )]
-    pub htlc: Box<Account<'info, HTLC>>,
+/// A key of the guardian settlement.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Attester {
+    pub kind: AttesterKind,
+    /// The Ed25519 public key, or for Secp256k1 the Ethereum address of the
+    /// key in the first 20 bytes.
+    pub key: [u8; 32],
+}
 
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+impl Attester {
+    pub fn is_valid(&self) -> bool {
+        self.key != [0u8; 32] && (self.kind == AttesterKind::Ed25519 || self.key[20..] == [0u8; 12])
+    }
 }
 
-#[derive(Accounts)]
-#[instruction(Id: [u8; 32])]
-pub struct Redeem<'info> {
-    #[account(mut)]
-    user_signing: Signer<'info>,
+/// A secp256k1 signature of an attester over the keccak256 of
+/// htlc_core::attestation::message.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AttesterSignature {
+    /// Index of the attester in the config.
+    pub attester: u8,
+    pub signature: [u8; 64],
+    /// v of the signature, 0/1 or 27/28.
+    pub recovery_id: u8,
+}
 
-    #[account(
-        mut,
-        seeds = [
-            Id.as_ref()
-        ],
-        bump,
-        has_one = src_receiver @HTLCError::NotReciever,
-        constraint = !htlc.redeemed @ HTLCError::AlreadyRedeemed,
-        constraint = !htlc.refunded @ HTLCError::AlreadyRefunded,
-    )]
-    pub htlc: Box<Account<'info, HTLC>>,
+/// Open HTLCs and locked lamports of a sender, at the PDA of
+/// `b"sender_stats"` and the sender.
+#[account]
+#[derive(Default)]
+pub struct SenderStats {
+    pub sender: Pubkey,
+    pub open_htlcs: u32,
+    pub locked: u64,
+}
 
-    ///CHECK: The reciever
-    #[account(mut)]
-    pub src_receiver: UncheckedAccount<'info>,
+impl SenderStats {
+    /// Counts a new HTLC of `amount`, failing if that exceeds a limit.
+    pub fn open(&mut self, amount: u64, max_open_htlcs: u32, max_locked: u64) -> Result<()> {
+        self.open_htlcs = self
+            .open_htlcs
+            .checked_add(1)
+            .ok_or(HTLCError::AmountOverflow)?;
+        require!(
+            max_open_htlcs == 0 || self.open_htlcs <= max_open_htlcs,
+            HTLCError::TooManyOpenHtlcs
+        );
+        self.add(amount, max_locked)
+    }
 
-    system_program: Program<'info, System>,
-    rent: Sysvar<'info, Rent>,
+    /// Counts `amount` more locked in an open HTLC, failing if that exceeds the limit.
+    pub fn add(&mut self, amount: u64, max_locked: u64) -> Result<()> {
+        self.locked = self
+            .locked
+            .checked_add(amount)
+            .ok_or(HTLCError::AmountOverflow)?;
+        require!(
+            max_locked == 0 || self.locked <= max_locked,
+            HTLCError::LockedLimitExceeded
+        );
+        Ok(())
+    }
+
+    /// Releases an HTLC of `amount` once it is redeemed or refunded.
+    pub fn release(&mut self, amount: u64) {
+        self.open_htlcs = self.open_htlcs.saturating_sub(1);
+        self.locked = self.locked.saturating_sub(amount);
+    }
 }
 
-#[derive(Accounts)]
-#[instruction(Id: [u8; 32])]
-pub struct Refund<'info> {
-    #[account(mut)]
-    user_signing: Signer<'info>,
+/// Releases a redeemed or refunded HTLC from the stats of its sender. HTLCs
+/// created before the stats were kept are not counted in them.
+fn release_sender_stats(stats: &mut Option<Account<SenderStats>>, htlc: &HTLC) -> Result<()> {
+    if htlc.counted != 0 {
+        stats
+            .as_mut()
+            .ok_or(HTLCError::SenderStatsRequired)?
+            .release(htlc.amount);
+    }
+    Ok(())
+}
 
-    #[account(mut,
-    seeds = [
-        Id.as_ref()
-    ],
-    bump,
-    has_one = sender @HTLCError::NotSender,
-    constraint = !htlc.refunded @ HTLCError::AlreadyRefunded,
-    constraint = !htlc.redeemed @ HTLCError::AlreadyRedeemed,
-    constraint = Clock::get().unwrap().unix_timestamp >= htlc.timelock.try_into().unwrap() @ HTLCError::NotPastTimeLock,
-    )]
-    pub htlc: Box<Account<'info, HTLC>>,
+/// spl-account-compression and the data of the instructions compressed HTLCs
+/// call on it. The spl-account-compression crate is built against anchor-lang
+/// 0.28 and can't be linked into this program, so the instructions are encoded
+/// as defined by version 0.2.0 of the crate: the Anchor discriminator, the
+/// first 8 bytes of sha256("global:<name>"), followed by the Borsh arguments.
+/// The accounts are those of its `Initialize` and `Modify` contexts, see
+/// compression_cpi. Check both against the crate before moving to another
+/// version of the program.
+pub mod account_compression {
+    anchor_lang::declare_id!("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK");
 
-    ///CHECK: The sender
-    #[account(mut)]
-    sender: UncheckedAccount<'info>,
+    const INIT_EMPTY_MERKLE_TREE: [u8; 8] = [191, 11, 119, 7, 180, 107, 220, 110];
+    const APPEND: [u8; 8] = [149, 120, 18, 222, 236, 225, 88, 203];
+    const REPLACE_LEAF: [u8; 8] = [204, 165, 76, 100, 73, 147, 0, 128];
 
-    system_program: Program<'info, System>,
-    rent: Sysvar<'info, Rent>,
+    /// `init_empty_merkle_tree(max_depth: u32, max_buffer_size: u32)`
+    pub fn init_empty_merkle_tree(max_depth: u32, max_buffer_size: u32) -> Vec<u8> {
+        [
+            &INIT_EMPTY_MERKLE_TREE[..],
+            &max_depth.to_le_bytes(),
+            &max_buffer_size.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    /// `append(leaf: [u8; 32])`
+    pub fn append(leaf: [u8; 32]) -> Vec<u8> {
+        [&APPEND[..], &leaf].concat()
+    }
+
+    /// `replace_leaf(root: [u8; 32], previous_leaf: [u8; 32], new_leaf: [u8; 32], index: u32)`
+    pub fn replace_leaf(
+        root: [u8; 32],
+        previous_leaf: [u8; 32],
+        new_leaf: [u8; 32],
+        index: u32,
+    ) -> Vec<u8> {
+        [
+            &REPLACE_LEAF[..],
+            &root,
+            &previous_leaf,
+            &new_leaf,
+            &index.to_le_bytes(),
+        ]
+        .concat()
+    }
+}
+pub mod noop {
+    anchor_lang::declare_id!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
 }
 
-#[derive(Accounts)]
-#[instruction(Id: [u8; 32])]
-pub struct AddLock<'info> {
+/// A concurrent Merkle tree of compressed HTLCs, at the PDA of
+/// `b"compressed_tree"` and the tree account. It is the authority of the tree
+/// and holds the lamports locked in its HTLCs.
+#[account]
+#[derive(Default)]
+pub struct CompressedTree {
+    pub merkle_tree: Pubkey,
+    pub bump: u8,
+    /// Index of the next leaf to be appended.
+    pub next_index: u32,
+}
+
+/// An HTLC stored as a leaf of a compressed tree. The leaf is the keccak hash
+/// of its Borsh serialization.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct CompressedHtlc {
+    pub Id: [u8; 32],
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub refund_to: Pubkey,
+    pub hashlock: [u8; 32],
+    pub secret: [u8; 32],
+    pub amount: u64,
+    pub timelock: u64,
+    pub timelock_kind: TimelockKind,
+    pub redeemed: bool,
+    pub refunded: bool,
+}
+
+impl CompressedHtlc {
+    /// The leaf of the HTLC in the tree.
+    pub fn leaf(&self) -> Result<[u8; 32]> {
+        Ok(keccak::hashv(&[&self.try_to_vec()?]).0)
+    }
+
+    /// The state of the HTLC for the rules of htlc_core. Compressed HTLCs are
+    /// always locked and have no commit deadline.
+    pub fn state(&self) -> htlc_core::State {
+        htlc_core::State {
+            hashlock: self.hashlock,
+            timelock: self.timelock,
+            commit_deadline: 0,
+            redeemed: self.redeemed,
+            refunded: self.refunded,
+        }
+    }
+}
+
+/// The accounts of a compressed tree that calls into spl-account-compression.
+pub struct CompressedAccounts<'a, 'info> {
+    pub compressed_tree: &'a Account<'info, CompressedTree>,
+    pub merkle_tree: &'a AccountInfo<'info>,
+    pub noop_program: &'a AccountInfo<'info>,
+    pub compression_program: &'a AccountInfo<'info>,
+}
+
+/// Calls an spl-account-compression instruction on a tree, signed by its
+/// CompressedTree PDA. The proof nodes are appended to the tree accounts.
+fn compression_cpi<'info>(
+    tree: &CompressedAccounts<'_, 'info>,
+    proof: &[AccountInfo<'info>],
+    data: Vec<u8>,
+) -> Result<()> {
+    let mut accounts = vec![
+        AccountMeta::new(tree.merkle_tree.key(), false),
+        AccountMeta::new_readonly(tree.compressed_tree.key(), true),
+        AccountMeta::new_readonly(tree.noop_program.key(), false),
+    ];
+    accounts.extend(
+        proof
+            .iter()
+            .map(|node| AccountMeta::new_readonly(node.key(), false)),
+    );
+    let mut infos = vec![
+        tree.merkle_tree.clone(),
+        tree.compressed_tree.to_account_info(),
+        tree.noop_program.clone(),
+        tree.compression_program.clone(),
+    ];
+    infos.extend_from_slice(proof);
+
+    let bump = [tree.compressed_tree.bump];
+    let seeds = [
+        b"compressed_tree".as_ref(),
+        tree.merkle_tree.key.as_ref(),
+        bump.as_ref(),
+    ];
+    invoke_signed(
+        &Instruction {
+            program_id: account_compression::ID,
+            accounts,
+            data,
+        },
+        &infos,
+        &[seeds.as_slice()],
+    )?;
+    Ok(())
+}
+
+/// Replaces the leaf of `htlc` at `index` with the leaf of `updated`. The
+/// compression program fails unless the proof shows the current leaf is `htlc`.
+fn replace_compressed<'info>(
+    tree: &CompressedAccounts<'_, 'info>,
+    proof: &[AccountInfo<'info>],
+    htlc: &CompressedHtlc,
+    updated: &CompressedHtlc,
+    index: u32,
+    root: [u8; 32],
+) -> Result<()> {
+    compression_cpi(
+        tree,
+        proof,
+        account_compression::replace_leaf(root, htlc.leaf()?, updated.leaf()?, index),
+    )
+}
+
+/// A destination chain of the registry, at the PDA of `b"chain"` and
+/// registry_key of its CAIP-2 id.
+#[account]
+pub struct ChainEntry {
+    pub chain_id: [u8; MAX_CHAIN_ID_LEN],
+    pub chain_id_len: u8,
+    /// Format of the addresses of the chain, checked against `dst_address`.
+    pub family: ChainFamily,
+    /// Whether new HTLCs can use the chain.
+    pub enabled: bool,
+}
+
+impl ChainEntry {
+    pub fn chain_id(&self) -> &[u8] {
+        &self.chain_id[..self.chain_id_len as usize]
+    }
+}
+
+/// The kind of chain a ChainEntry is, which determines the address format.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum ChainFamily {
+    /// Addresses are not validated.
+    #[default]
+    Other,
+    /// 0x and 40 hex digits, with the EIP-55 checksum if mixed case.
+    Evm,
+    /// Segwit bech32 or bech32m, or base58check P2PKH and P2SH, with the
+    /// prefixes of the network given by the `bip122` chain id.
+    Bitcoin,
+    /// 0x and up to 64 hex digits of a felt below 2^251.
+    Starknet,
+    /// 0x and 64 hex digits.
+    Sui,
+    /// 0x and 64 hex digits of a b256 address.
+    Fuel,
+    /// base58 of a 32 byte public key.
+    Solana,
+}
+
+impl ChainFamily {
+    /// Whether the family can be used for the chain `chain_id`. Bitcoin chains
+    /// have to be a network whose address prefixes are known.
+    pub fn is_valid_chain(&self, chain_id: &str) -> bool {
+        *self != ChainFamily::Bitcoin || BitcoinNetwork::from_chain_id(chain_id).is_some()
+    }
+
+    pub fn is_valid_address(&self, chain_id: &str, address: &str) -> bool {
+        match self {
+            ChainFamily::Other => true,
+            ChainFamily::Evm => route::is_evm_address(address),
+            ChainFamily::Bitcoin => BitcoinNetwork::from_chain_id(chain_id)
+                .is_some_and(|network| route::is_bitcoin_address(network, address)),
+            ChainFamily::Starknet => route::is_starknet_address(address),
+            ChainFamily::Sui | ChainFamily::Fuel => route::is_hex32_address(address),
+            ChainFamily::Solana => route::is_solana_address(address),
+        }
+    }
+}
+
+/// An asset of the registry, at the PDA of `b"asset"` and registry_key of its
+/// CAIP-19 id. Its chain is registered as a ChainEntry.
+#[account]
+pub struct AssetEntry {
+    pub asset_id: [u8; MAX_ASSET_ID_LEN],
+    pub asset_id_len: u8,
+    /// Whether new HTLCs can use the asset.
+    pub enabled: bool,
+}
+
+impl AssetEntry {
+    pub fn asset_id(&self) -> &[u8] {
+        &self.asset_id[..self.asset_id_len as usize]
+    }
+}
+
+/// Fails unless the route of a new HTLC only uses enabled entries of the
+/// registry, with the destination asset on the destination chain.
+fn check_route(
+    dst_chain_entry: &ChainEntry,
+    dst_asset_entry: &AssetEntry,
+    src_asset_entry: &AssetEntry,
+    dst_chain: &str,
+    dst_asset: &str,
+    dst_address: &str,
+    src_asset: &str,
+) -> Result<()> {
+    require!(
+        dst_chain_entry.enabled && dst_chain_entry.chain_id() == dst_chain.as_bytes(),
+        HTLCError::ChainNotRegistered
+    );
+    require!(
+        dst_asset_entry.enabled
+            && dst_asset_entry.asset_id() == dst_asset.as_bytes()
+            && caip19_chain(dst_asset) == Some(dst_chain),
+        HTLCError::AssetNotRegistered
+    );
+    require!(
+        src_asset_entry.enabled && src_asset_entry.asset_id() == src_asset.as_bytes(),
+        HTLCError::AssetNotRegistered
+    );
+    require!(
+        dst_chain_entry
+            .family
+            .is_valid_address(dst_chain, dst_address),
+        HTLCError::InvalidDestinationAddress
+    );
+    Ok(())
+}
+
+/// The destination chain HTLC of a swap, at the PDA of `b"counterparty"` and
+/// the Id of the Solana HTLC.
+#[account]
+pub struct Counterparty {
+    pub Id: [u8; 32],
+    pub counterparty_id: [u8; 32],
+    pub lock_tx_hash: [u8; MAX_TX_HASH_LEN],
+    pub lock_tx_hash_len: u8,
+}
+
+/// Maps a hashlock to the Id of the HTLC that uses it.
+#[account]
+#[derive(Default)]
+pub struct HashlockIndex {
+    pub id: [u8; 32],
+}
+
+/// The clock an HTLC timelock is measured against.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum TimelockKind {
+    /// UNIX epoch seconds, checked against `Clock::unix_timestamp`.
+    #[default]
+    Timestamp,
+    /// Slot height, checked against `Clock::slot`.
+    Slot,
+}
+
+impl TimelockKind {
+    /// Returns the current value of the clock field this kind is measured against.
+    pub fn now(&self, clock: &Clock) -> u64 {
+        match self {
+            TimelockKind::Timestamp => clock.unix_timestamp.try_into().unwrap(),
+            TimelockKind::Slot => clock.slot,
+        }
+    }
+}
+
+impl From<u8> for TimelockKind {
+    fn from(kind: u8) -> TimelockKind {
+        match kind {
+            1 => TimelockKind::Slot,
+            _ => TimelockKind::Timestamp,
+        }
+    }
+}
+
+/// Version of the HTLC account layout written by this program. Version 0 is
+/// the Borsh layout that preceded it (HTLCV0), which `migrate` rewrites.
+pub const HTLC_VERSION: u8 = 1;
+
+/// Maximum number of split receivers of an HTLC.
+pub const MAX_SPLITS: usize = 4;
+/// Split shares are in basis points of the amount.
+pub const SHARE_DENOMINATOR: u64 = 10_000;
+
+/// Capacity in bytes of the fixed size string fields of the HTLC.
+pub const MAX_ADDRESS_LEN: usize = 96;
+pub const MAX_CHAIN_LEN: usize = 32;
+pub const MAX_ASSET_LEN: usize = 64;
+/// Capacity of the route fields together with their extensions, enough for
+/// CAIP-2 chain ids and CAIP-19 asset ids.
+pub const MAX_CHAIN_ID_LEN: usize = MAX_CHAIN_LEN + 16;
+pub const MAX_ASSET_ID_LEN: usize = MAX_ASSET_LEN + 64;
+/// Capacity in bytes of the metadata of the HTLC.
+pub const MAX_METADATA_LEN: usize = 64;
+/// Most attesters of the guardian settlement.
+pub const MAX_ATTESTERS: usize = 10;
+/// Capacity of the lock transaction hash of a Counterparty, enough for a
+/// Solana signature.
+pub const MAX_TX_HASH_LEN: usize = 64;
+
+/// HTLC account, read in place without deserializing it.
+/// Strings are stored as fixed size byte arrays and a length. The single
+/// byte fields come first so the version stays right after the discriminator,
+/// new fields are only ever appended.
+#[account(zero_copy)]
+pub struct HTLC {
+    pub version: u8,
+    /// `TimelockKind` as u8.
+    pub timelock_kind: u8,
+    /// 1 once redeemed.
+    pub redeemed: u8,
+    /// 1 once refunded.
+    pub refunded: u8,
+    pub dst_address_len: u8,
+    pub dst_chain_len: u8,
+    pub dst_asset_len: u8,
+    pub src_asset_len: u8,
+    pub amount: u64,
+    pub timelock: u64,
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub hashlock: [u8; 32],
+    pub secret: [u8; 32],
+    pub dst_address: [u8; MAX_ADDRESS_LEN],
+    pub dst_chain: [u8; MAX_CHAIN_LEN],
+    pub dst_asset: [u8; MAX_ASSET_LEN],
+    pub src_asset: [u8; MAX_ASSET_LEN],
+    /// Ethereum address that can authorize add_lock_sig, zero if none.
+    pub evm_signer: [u8; 20],
+    /// Number of split receivers in use.
+    pub split_len: u8,
+    pub metadata_len: u8,
+    /// 1 if counted in the sender stats.
+    pub counted: u8,
+    pub reserved: [u8; 1],
+    /// Address refunds are paid to, the sender unless set at creation.
+    pub refund_to: Pubkey,
+    pub split_receivers: [Pubkey; MAX_SPLITS],
+    /// Share of each split receiver in basis points, see Split.
+    pub split_shares: [u16; MAX_SPLITS],
+    /// Time or slot after which a commit without a hashlock can be refunded,
+    /// zero if none.
+    pub commit_deadline: u64,
+    /// Opaque bytes of the sender, `metadata_len` of them are used.
+    pub metadata: [u8; MAX_METADATA_LEN],
+    /// Continuations of `dst_chain`, `dst_asset` and `src_asset`, their
+    /// lengths include the bytes stored here.
+    pub dst_chain_ext: [u8; MAX_CHAIN_ID_LEN - MAX_CHAIN_LEN],
+    pub dst_asset_ext: [u8; MAX_ASSET_ID_LEN - MAX_ASSET_LEN],
+    pub src_asset_ext: [u8; MAX_ASSET_ID_LEN - MAX_ASSET_LEN],
+}
+
+impl HTLC {
+    /// Account size including the discriminator.
+    pub const SPACE: usize = 8 + size_of::<HTLC>();
+
+    /// Whether `htlc` is stored in the current layout. An HTLC still in the
+    /// V0 layout is smaller than `HTLC`, so it is checked before `load` and
+    /// has to be migrated first.
+    pub fn is_current(htlc: &AccountLoader<HTLC>) -> Result<bool> {
+        Ok(
+            htlc.to_account_info().data_len() >= HTLC::SPACE
+                && htlc.load()?.version == HTLC_VERSION,
+        )
+    }
+
+    /// The sender of `htlc`, for seeds that are derived before its
+    /// constraints are checked.
+    pub fn current_sender(htlc: &AccountLoader<HTLC>) -> Result<Pubkey> {
+        require!(HTLC::is_current(htlc)?, HTLCError::UnknownLayout);
+        Ok(htlc.load()?.sender)
+    }
+
+    pub fn timelock_kind(&self) -> TimelockKind {
+        self.timelock_kind.into()
+    }
+
+    /// The state of the HTLC for the rules of htlc_core.
+    pub fn state(&self) -> htlc_core::State {
+        htlc_core::State {
+            hashlock: self.hashlock,
+            timelock: self.timelock,
+            commit_deadline: self.commit_deadline,
+            redeemed: self.redeemed != 0,
+            refunded: self.refunded != 0,
+        }
+    }
+
+    /// Whether the timelock has passed and the HTLC can be refunded.
+    pub fn is_expired(&self, clock: &Clock) -> bool {
+        self.state().is_expired(self.timelock_kind().now(clock))
+    }
+
+    /// Whether the HTLC is still without a hashlock after its commit deadline.
+    pub fn is_past_commit_deadline(&self, clock: &Clock) -> bool {
+        self.state()
+            .is_past_commit_deadline(self.timelock_kind().now(clock))
+    }
+
+    /// Whether the sender can refund the HTLC, after the timelock or early
+    /// after the commit deadline.
+    pub fn is_refundable(&self, clock: &Clock) -> bool {
+        self.state().is_refundable(self.timelock_kind().now(clock))
+    }
+
+    /// Sets the string fields, failing if one does not fit its capacity.
+    pub fn set_route(
+        &mut self,
+        dst_chain: &str,
+        dst_asset: &str,
+        dst_address: &str,
+        src_asset: &str,
+    ) -> Result<()> {
+        (self.dst_chain, self.dst_chain_ext, self.dst_chain_len) = pack_split(dst_chain)?;
+        (self.dst_asset, self.dst_asset_ext, self.dst_asset_len) = pack_split(dst_asset)?;
+        (self.dst_address, self.dst_address_len) = pack_str(dst_address)?;
+        (self.src_asset, self.src_asset_ext, self.src_asset_len) = pack_split(src_asset)?;
+        Ok(())
+    }
+
+    /// Copies an HTLC read from a Borsh layout. String fields longer than
+    /// their capacity are truncated rather than leaving the funds in an
+    /// account that can't be migrated.
+    pub fn set_from_legacy(&mut self, htlc: HTLCV0) {
+        (self.dst_chain, self.dst_chain_len) = pack_str_truncated(&htlc.dst_chain);
+        (self.dst_asset, self.dst_asset_len) = pack_str_truncated(&htlc.dst_asset);
+        (self.dst_address, self.dst_address_len) = pack_str_truncated(&htlc.dst_address);
+        (self.src_asset, self.src_asset_len) = pack_str_truncated(&htlc.src_asset);
+        self.sender = htlc.sender;
+        self.src_receiver = htlc.src_receiver;
+        self.refund_to = htlc.sender;
+        self.hashlock = htlc.hashlock;
+        self.secret = htlc.secret;
+        self.amount = htlc.amount;
+        self.timelock = htlc.timelock;
+        self.timelock_kind = TimelockKind::Timestamp as u8;
+        self.redeemed = htlc.redeemed as u8;
+        self.refunded = htlc.refunded as u8;
+    }
+
+    /// Sets the split receivers, failing if there are more than MAX_SPLITS,
+    /// a share is zero or the shares add up to more than SHARE_DENOMINATOR.
+    pub fn set_splits(&mut self, splits: &[Split]) -> Result<()> {
+        require!(splits.len() <= MAX_SPLITS, HTLCError::InvalidSplit);
+        let mut total = 0;
+        for (i, split) in splits.iter().enumerate() {
+            require!(split.share != 0, HTLCError::InvalidSplit);
+            self.split_receivers[i] = split.receiver;
+            self.split_shares[i] = split.share;
+            total += split.share as u64;
+        }
+        require!(total <= SHARE_DENOMINATOR, HTLCError::InvalidSplit);
+        self.split_len = splits.len() as u8;
+        Ok(())
+    }
+
+    pub fn splits(&self) -> Vec<Split> {
+        (0..self.split_len as usize)
+            .map(|i| Split {
+                receiver: self.split_receivers[i],
+                share: self.split_shares[i],
+            })
+            .collect()
+    }
+
+    /// Returns what each split receiver gets on redeem, rounded down, and what
+    /// is left for the primary receiver, which gets the rounding dust.
+    pub fn payouts(&self) -> (Vec<(Pubkey, u64)>, u64) {
+        let splits: Vec<(Pubkey, u64)> = self
+            .splits()
+            .iter()
+            .map(|split| {
+                let part = self.amount as u128 * split.share as u128 / SHARE_DENOMINATOR as u128;
+                (split.receiver, part as u64)
+            })
+            .collect();
+        let rest = self.amount - splits.iter().map(|(_, part)| part).sum::<u64>();
+        (splits, rest)
+    }
+
+    /// Returns the summary reported by get_details_batch for an account.
+    pub fn summary_of(account: &AccountInfo, clock: &Clock) -> HTLCSummary {
+        if account.owner != &ID {
+            return HTLCSummary::default();
+        }
+        account
+            .try_borrow_data()
+            .ok()
+            .and_then(|data| HTLC::read_any_version(&data).ok())
+            .map(|htlc| htlc.summary(clock))
+            .unwrap_or_default()
+    }
+
+    pub fn summary(&self, clock: &Clock) -> HTLCSummary {
+        let status = if self.redeemed != 0 {
+            HTLCStatus::Redeemed
+        } else if self.refunded != 0 {
+            HTLCStatus::Refunded
+        } else if self.is_refundable(clock) {
+            HTLCStatus::Expired
+        } else if self.hashlock == [0u8; 32] {
+            HTLCStatus::Committed
+        } else {
+            HTLCStatus::Locked
+        };
+        HTLCSummary {
+            status,
+            amount: self.amount,
+            timelock: self.timelock,
+        }
+    }
+
+    pub fn details(&self) -> HTLCDetails {
+        HTLCDetails {
+            version: self.version,
+            dst_address: unpack_str(&self.dst_address, self.dst_address_len),
+            dst_chain: unpack_split(&self.dst_chain, &self.dst_chain_ext, self.dst_chain_len),
+            dst_asset: unpack_split(&self.dst_asset, &self.dst_asset_ext, self.dst_asset_len),
+            src_asset: unpack_split(&self.src_asset, &self.src_asset_ext, self.src_asset_len),
+            sender: self.sender,
+            src_receiver: self.src_receiver,
+            hashlock: self.hashlock,
+            secret: self.secret,
+            amount: self.amount,
+            timelock: self.timelock,
+            timelock_kind: self.timelock_kind(),
+            redeemed: self.redeemed != 0,
+            refunded: self.refunded != 0,
+            evm_signer: self.evm_signer,
+            refund_to: self.refund_to,
+            splits: self.splits(),
+            commit_deadline: self.commit_deadline,
+            metadata: self.metadata[..self.metadata_len as usize].to_vec(),
+        }
+    }
+
+    /// Reads an HTLC stored in any known layout into the current one,
+    /// without rewriting the account. `version` stays the stored version.
+    pub fn read_any_version(data: &[u8]) -> Result<HTLC> {
+        let (version, legacy) = HTLC::stored_layout(data)?;
+        let mut htlc = HTLC::zeroed();
+        match legacy {
+            Some(legacy) => {
+                htlc.set_from_legacy(legacy);
+                htlc.version = version;
+            }
+            None => bytemuck::bytes_of_mut(&mut htlc).copy_from_slice(&data[8..HTLC::SPACE]),
+        }
+        Ok(htlc)
+    }
+
+    /// Returns the layout version an HTLC account is stored in, together with
+    /// its contents if it is still stored in the Borsh layout of version 0.
+    pub fn stored_layout(data: &[u8]) -> Result<(u8, Option<HTLCV0>)> {
+        require!(
+            data.len() > 8 && data[..8] == HTLC::DISCRIMINATOR,
+            HTLCError::UnknownLayout
+        );
+        if data.len() >= HTLC::SPACE && data[8] == HTLC_VERSION {
+            return Ok((HTLC_VERSION, None));
+        }
+        let htlc = HTLCV0::deserialize(&mut &data[8..]).map_err(|_| HTLCError::UnknownLayout)?;
+        Ok((0, Some(htlc)))
+    }
+}
+
+/// Marks `htlc` as redeemed with `secret` and pays out the split receivers,
+/// passed as `split_receivers`, and the src_receiver.
+fn pay_redeem(
+    htlc: &AccountLoader<HTLC>,
+    src_receiver: &AccountInfo,
+    sender_stats: &mut Option<Account<SenderStats>>,
+    split_receivers: &[AccountInfo],
+    secret: [u8; 32],
+) -> Result<()> {
+    let mut htlc_data = htlc.load_mut()?;
+    htlc_data.redeemed = 1;
+    htlc_data.secret = secret;
+
+    let (splits, rest) = htlc_data.payouts();
+    release_sender_stats(sender_stats, &htlc_data)?;
+    drop(htlc_data);
+
+    pay_splits(&htlc.to_account_info(), split_receivers, &splits)?;
+    htlc.sub_lamports(rest)?;
+    src_receiver.add_lamports(rest)?;
+
+    Ok(())
+}
+
+/// Copies `value` into a fixed size field, failing if it does not fit.
+fn pack_str<const N: usize>(value: &str) -> Result<([u8; N], u8)> {
+    pack_bytes(value.as_bytes())
+}
+
+/// Copies `value` into a fixed size field, failing if it does not fit.
+fn pack_bytes<const N: usize>(value: &[u8]) -> Result<([u8; N], u8)> {
+    require!(value.len() <= N, HTLCError::FieldTooLong);
+    let mut field = [0u8; N];
+    field[..value.len()].copy_from_slice(value);
+    Ok((field, value.len() as u8))
+}
+
+/// Copies as much of `value` as fits into a fixed size field.
+fn pack_str_truncated<const N: usize>(value: &str) -> ([u8; N], u8) {
+    let len = value.len().min(N);
+    let mut field = [0u8; N];
+    field[..len].copy_from_slice(&value.as_bytes()[..len]);
+    (field, len as u8)
+}
+
+fn unpack_str(field: &[u8], len: u8) -> String {
+    String::from_utf8_lossy(&field[..len as usize]).into_owned()
+}
+
+/// Copies `value` into a fixed size field and its extension, failing if it
+/// does not fit both.
+fn pack_split<const N: usize, const M: usize>(value: &str) -> Result<([u8; N], [u8; M], u8)> {
+    let (head, tail) = value.as_bytes().split_at(value.len().min(N));
+    let (field, _) = pack_bytes::<N>(head)?;
+    let (ext, _) = pack_bytes::<M>(tail)?;
+    Ok((field, ext, value.len() as u8))
+}
+
+fn unpack_split(field: &[u8], ext: &[u8], len: u8) -> String {
+    unpack_str(&[field, ext].concat(), len)
+}
+
+/// Pays the split receivers of a redeemed HTLC, passed as remaining accounts
+/// in the order of the splits.
+fn pay_splits(
+    htlc: &AccountInfo,
+    accounts: &[AccountInfo],
+    splits: &[(Pubkey, u64)],
+) -> Result<()> {
+    require!(accounts.len() >= splits.len(), HTLCError::NotSplitReceiver);
+    for ((receiver, part), account) in splits.iter().zip(accounts) {
+        require!(account.key == receiver, HTLCError::NotSplitReceiver);
+        htlc.sub_lamports(*part)?;
+        account.add_lamports(*part)?;
+    }
+    Ok(())
+}
+
+/// Whether the sender's program already credited `amount` lamports to the HTLC
+/// account, on top of its rent exemption and the `locked` amount. The funds are
+/// then not transferred from the sender again. A new HTLC has to be credited
+/// with its rent exemption (`Rent::minimum_balance(HTLC::SPACE)`) plus the amount.
+pub fn is_prefunded(htlc: &AccountInfo, locked: u64, amount: u64) -> Result<bool> {
+    let rent = Rent::get()?.minimum_balance(htlc.data_len());
+    Ok(htlc.lamports().saturating_sub(rent).saturating_sub(locked) >= amount)
+}
+
+/// The secp256k1 key of a sender that signs with an EVM wallet.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EvmSigner {
+    /// Uncompressed public key without the 0x04 prefix.
+    PublicKey([u8; 64]),
+    Address([u8; 20]),
+}
+
+impl EvmSigner {
+    pub fn address(&self) -> [u8; 20] {
+        match self {
+            EvmSigner::PublicKey(key) => evm_address(key),
+            EvmSigner::Address(address) => *address,
+        }
+    }
+}
+
+/// How an EVM wallet signed the add_lock_sig message.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EvmMessageKind {
+    /// EIP-712 typed data, as eth_signTypedData_v4 signs it.
+    TypedData,
+    /// EIP-191 personal message of the EIP-712 digest, as personal_sign signs it.
+    PersonalMessage,
+}
+
+/// Returns the digest an EVM wallet signs to authorize add_lock_sig.
+/// The message is the addLockMsg of the EVM contracts. Solana has no chainId or
+/// verifyingContract, so the EIP-712 domain is salted with the program id instead.
+pub fn add_lock_digest(
+    kind: EvmMessageKind,
+    Id: &[u8; 32],
+    hashlock: &[u8; 32],
+    timelock: u64,
+) -> [u8; 32] {
+    let domain_separator = keccak::hashv(&[
+        keccak::hash(b"EIP712Domain(string name,string version,bytes32 salt)").as_ref(),
+        keccak::hash(b"LayerswapV8").as_ref(),
+        keccak::hash(b"1").as_ref(),
+        ID.as_ref(),
+    ]);
+    let mut timelock_word = [0u8; 32];
+    timelock_word[24..].copy_from_slice(&timelock.to_be_bytes());
+    let struct_hash = keccak::hashv(&[
+        keccak::hash(b"addLockMsg(bytes32 Id,bytes32 hashlock,uint256 timelock)").as_ref(),
+        Id,
+        hashlock,
+        &timelock_word,
+    ]);
+    let digest = keccak::hashv(&[b"\x19\x01", domain_separator.as_ref(), struct_hash.as_ref()]);
+    match kind {
+        EvmMessageKind::TypedData => digest.to_bytes(),
+        EvmMessageKind::PersonalMessage => {
+            keccak::hashv(&[b"\x19Ethereum Signed Message:\n32", digest.as_ref()]).to_bytes()
+        }
+    }
+}
+
+/// Returns the Ethereum address of an uncompressed secp256k1 public key.
+fn evm_address(key: &[u8; 64]) -> [u8; 20] {
+    keccak::hash(key).to_bytes()[12..].try_into().unwrap()
+}
+
+/// Recovers the Ethereum address that signed `digest`.
+fn recover_evm_address(
+    digest: &[u8; 32],
+    signature: &[u8; 64],
+    recovery_id: u8,
+) -> Result<[u8; 20]> {
+    let recovery_id = if recovery_id >= 27 {
+        recovery_id - 27
+    } else {
+        recovery_id
+    };
+    let key = secp256k1_recover(digest, recovery_id, signature)
+        .map_err(|_| HTLCError::InvalidSignature)?;
+    Ok(evm_address(&key.0))
+}
+
+/// A receiver that gets `share` basis points of the amount of an HTLC when
+/// it is redeemed. The primary receiver, src_receiver, gets the rest.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Split {
+    pub receiver: Pubkey,
+    pub share: u16,
+}
+
+/// HTLC details returned by getDetails. `version` is the layout version of the
+/// HTLC, fields are only ever appended together with a version bump.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct HTLCDetails {
+    pub version: u8,
+    pub dst_address: String,
+    pub dst_chain: String,
+    pub dst_asset: String,
+    pub src_asset: String,
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub hashlock: [u8; 32],
+    pub secret: [u8; 32],
+    pub amount: u64,
+    pub timelock: u64,
+    pub timelock_kind: TimelockKind,
+    pub redeemed: bool,
+    pub refunded: bool,
+    pub evm_signer: [u8; 20],
+    pub refund_to: Pubkey,
+    pub splits: Vec<Split>,
+    pub commit_deadline: u64,
+    pub metadata: Vec<u8>,
+}
+
+/// Maximum number of HTLCs get_details_batch reports on, bounded by the
+/// 1024 bytes of return data.
+pub const MAX_BATCH_LEN: usize = 60;
+
+/// State of an HTLC as reported by get_details_batch.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum HTLCStatus {
+    /// The account is not an HTLC of this program, or was closed.
+    #[default]
+    Missing,
+    /// Committed, waiting for the hashlock.
+    Committed,
+    /// Hashlock set, can be redeemed.
+    Locked,
+    /// Timelock passed, can be refunded.
+    Expired,
+    Redeemed,
+    Refunded,
+}
+
+/// HTLC summary returned by get_details_batch.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct HTLCSummary {
+    pub status: HTLCStatus,
+    pub amount: u64,
+    pub timelock: u64,
+}
+
+/// Borsh HTLC account layout before the version field was added (version 0).
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct HTLCV0 {
+    pub dst_address: String,
+    pub dst_chain: String,
+    pub dst_asset: String,
+    pub src_asset: String,
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub hashlock: [u8; 32],
+    pub secret: [u8; 32],
+    pub amount: u64,
+    pub timelock: u64,
+    pub redeemed: bool,
+    pub refunded: bool,
+}
+
+#[derive(Accounts)]
+pub struct GetCommitId<'info> {
+    ///CHECK: The sender
+    pub sender: UncheckedAccount<'info>,
+    ///CHECK: The reciever
+    pub receiver: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32], commit_bump: u8)]
+pub struct Commit<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = HTLC::SPACE,
+        seeds = [
+            Id.as_ref()
+        ],
+        bump,
+    )]
+    pub htlc: AccountLoader<'info, HTLC>,
+
+    pub dst_chain_entry: Box<Account<'info, ChainEntry>>,
+    pub dst_asset_entry: Box<Account<'info, AssetEntry>>,
+    pub src_asset_entry: Box<Account<'info, AssetEntry>>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = size_of::<SenderStats>() + 8,
+        seeds = [
+            b"sender_stats".as_ref(),
+            sender.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub sender_stats: Box<Account<'info, SenderStats>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32], hashlock: [u8; 32])]
+pub struct Lock<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = HTLC::SPACE,
+        seeds = [
+            Id.as_ref()
+        ],
+        bump,
+    )]
+    pub htlc: AccountLoader<'info, HTLC>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = size_of::<HashlockIndex>() + 8,
+        seeds = [
+            b"hashlock_index".as_ref(),
+            sender.key().as_ref(),
+            hashlock.as_ref()
+        ],
+        bump,
+        constraint = hashlock_index.id == [0u8; 32] @ HTLCError::HashlockAlreadyUsed,
+    )]
+    pub hashlock_index: Box<Account<'info, HashlockIndex>>,
+
+    pub dst_chain_entry: Box<Account<'info, ChainEntry>>,
+    pub dst_asset_entry: Box<Account<'info, AssetEntry>>,
+    pub src_asset_entry: Box<Account<'info, AssetEntry>>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = size_of::<SenderStats>() + 8,
+        seeds = [
+            b"sender_stats".as_ref(),
+            sender.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub sender_stats: Box<Account<'info, SenderStats>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct Redeem<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            Id.as_ref()
+        ],
+        bump,
+        constraint = HTLC::is_current(&htlc)? @ HTLCError::UnknownLayout,
+        constraint = htlc.load()?.src_receiver == src_receiver.key() @ HTLCError::NotReciever,
+        constraint = htlc.load()?.redeemed == 0 @ HTLCError::AlreadyRedeemed,
+        constraint = htlc.load()?.refunded == 0 @ HTLCError::AlreadyRefunded,
+    )]
+    pub htlc: AccountLoader<'info, HTLC>,
+
+    ///CHECK: The reciever
+    #[account(mut)]
+    pub src_receiver: UncheckedAccount<'info>,
+
+    /// Stats of the sender, required if the HTLC is counted in them.
+    #[account(
+        mut,
+        constraint = sender_stats.sender == htlc.load()?.sender @ HTLCError::NotSender,
+    )]
+    pub sender_stats: Option<Account<'info, SenderStats>>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+    /// Settings with the attesters, only needed by redeem_attested.
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Option<Box<Account<'info, Config>>>,
+    ///CHECK: The instructions sysvar, only needed by redeem_attested with Ed25519 attesters.
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+#[instruction(hashlock: [u8; 32])]
+pub struct RedeemByHashlock<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+
+    #[account(
+        seeds = [
+            b"hashlock_index".as_ref(),
+            sender.key().as_ref(),
+            hashlock.as_ref()
+        ],
+        bump,
+    )]
+    pub hashlock_index: Box<Account<'info, HashlockIndex>>,
+    #[account(
+        mut,
+        seeds = [
+            hashlock_index.id.as_ref()
+        ],
+        bump,
+        constraint = HTLC::is_current(&htlc)? @ HTLCError::UnknownLayout,
+        constraint = htlc.load()?.sender == sender.key() @ HTLCError::NotSender,
+        constraint = htlc.load()?.src_receiver == src_receiver.key() @ HTLCError::NotReciever,
+        constraint = htlc.load()?.hashlock == hashlock @ HTLCError::HashlockNoMatch,
+        constraint = htlc.load()?.redeemed == 0 @ HTLCError::AlreadyRedeemed,
+        constraint = htlc.load()?.refunded == 0 @ HTLCError::AlreadyRefunded,
+    )]
+    pub htlc: AccountLoader<'info, HTLC>,
+
+    ///CHECK: The sender
+    pub sender: UncheckedAccount<'info>,
+
+    ///CHECK: The reciever
+    #[account(mut)]
+    pub src_receiver: UncheckedAccount<'info>,
+
+    /// Stats of the sender, required if the HTLC is counted in them.
+    #[account(
+        mut,
+        constraint = sender_stats.sender == htlc.load()?.sender @ HTLCError::NotSender,
+    )]
+    pub sender_stats: Option<Account<'info, SenderStats>>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct Refund<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+
+    #[account(mut,
+    seeds = [
+        Id.as_ref()
+    ],
+    bump,
+    constraint = HTLC::is_current(&htlc)? @ HTLCError::UnknownLayout,
+    constraint = htlc.load()?.sender == sender.key() @ HTLCError::NotSender,
+    constraint = htlc.load()?.refund_to == refund_to.key() @ HTLCError::NotRefundTo,
+    constraint = htlc.load()?.refunded == 0 @ HTLCError::AlreadyRefunded,
+    constraint = htlc.load()?.redeemed == 0 @ HTLCError::AlreadyRedeemed,
+    constraint = htlc.load()?.is_refundable(&Clock::get().unwrap()) @ HTLCError::NotPastTimeLock,
+    )]
+    pub htlc: AccountLoader<'info, HTLC>,
+
+    ///CHECK: The sender
+    #[account(mut)]
+    sender: UncheckedAccount<'info>,
+    ///CHECK: The refund destination of the HTLC, the sender unless set at creation.
+    #[account(mut)]
+    refund_to: UncheckedAccount<'info>,
+
+    /// Stats of the sender, required if the HTLC is counted in them.
+    #[account(
+        mut,
+        constraint = sender_stats.sender == htlc.load()?.sender @ HTLCError::NotSender,
+    )]
+    pub sender_stats: Option<Account<'info, SenderStats>>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32], hashlock: [u8; 32])]
+pub struct AddLock<'info> {
     sender: Signer<'info>,
     #[account(mut)]
     payer: Signer<'info>,
@@ -388,17 +2165,469 @@ pub struct AddLock<'info> {
         Id.as_ref()
     ],
     bump,
-    constraint = !htlc.redeemed @ HTLCError::AlreadyRedeemed,
-    constraint = !htlc.refunded @ HTLCError::AlreadyRefunded,
-    constraint = htlc.sender == sender.key() @ HTLCError::UnauthorizedAccess,
-    constraint = htlc.hashlock == [0u8;32] @ HTLCError::HashlockAlreadySet,
+    constraint = HTLC::is_current(&htlc)? @ HTLCError::UnknownLayout,
+    constraint = htlc.load()?.redeemed == 0 @ HTLCError::AlreadyRedeemed,
+    constraint = htlc.load()?.refunded == 0 @ HTLCError::AlreadyRefunded,
+    constraint = htlc.load()?.sender == sender.key() @ HTLCError::UnauthorizedAccess,
+    constraint = htlc.load()?.hashlock == [0u8; 32] @ HTLCError::HashlockAlreadySet,
+    )]
+    pub htlc: AccountLoader<'info, HTLC>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = size_of::<HashlockIndex>() + 8,
+        seeds = [
+            b"hashlock_index".as_ref(),
+            sender.key().as_ref(),
+            hashlock.as_ref()
+        ],
+        bump,
+        constraint = hashlock_index.id == [0u8; 32] @ HTLCError::HashlockAlreadyUsed,
     )]
-    pub htlc: Box<Account<'info, HTLC>>,
+    pub hashlock_index: Box<Account<'info, HashlockIndex>>,
 
     system_program: Program<'info, System>,
     rent: Sysvar<'info, Rent>,
 }
 
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32], hashlock: [u8; 32])]
+pub struct AddLockSig<'info> {
+    #[account(mut)]
+    payer: Signer<'info>,
+    #[account(mut,
+    seeds = [
+        Id.as_ref()
+    ],
+    bump,
+    constraint = HTLC::is_current(&htlc)? @ HTLCError::UnknownLayout,
+    constraint = htlc.load()?.redeemed == 0 @ HTLCError::AlreadyRedeemed,
+    constraint = htlc.load()?.refunded == 0 @ HTLCError::AlreadyRefunded,
+    constraint = htlc.load()?.evm_signer != [0u8; 20] @ HTLCError::NoEvmSigner,
+    constraint = htlc.load()?.hashlock == [0u8; 32] @ HTLCError::HashlockAlreadySet,
+    )]
+    pub htlc: AccountLoader<'info, HTLC>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = size_of::<HashlockIndex>() + 8,
+        seeds = [
+            b"hashlock_index".as_ref(),
+            HTLC::current_sender(&htlc)?.as_ref(),
+            hashlock.as_ref()
+        ],
+        bump,
+        constraint = hashlock_index.id == [0u8; 32] @ HTLCError::HashlockAlreadyUsed,
+    )]
+    pub hashlock_index: Box<Account<'info, HashlockIndex>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct TopUp<'info> {
+    #[account(mut)]
+    sender: Signer<'info>,
+    #[account(mut,
+    seeds = [
+        Id.as_ref()
+    ],
+    bump,
+    constraint = HTLC::is_current(&htlc)? @ HTLCError::UnknownLayout,
+    constraint = htlc.load()?.redeemed == 0 @ HTLCError::AlreadyRedeemed,
+    constraint = htlc.load()?.refunded == 0 @ HTLCError::AlreadyRefunded,
+    constraint = htlc.load()?.sender == sender.key() @ HTLCError::UnauthorizedAccess,
+    constraint = htlc.load()?.hashlock == [0u8; 32] @ HTLCError::HashlockAlreadySet,
+    )]
+    pub htlc: AccountLoader<'info, HTLC>,
+
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    /// Stats of the sender, required if the HTLC is counted in them.
+    #[account(
+        mut,
+        constraint = sender_stats.sender == htlc.load()?.sender @ HTLCError::NotSender,
+    )]
+    pub sender_stats: Option<Account<'info, SenderStats>>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct LinkCounterparty<'info> {
+    #[account(mut)]
+    pub src_receiver: Signer<'info>,
+    #[account(
+        seeds = [
+            Id.as_ref()
+        ],
+        bump,
+        constraint = HTLC::is_current(&htlc)? @ HTLCError::UnknownLayout,
+        constraint = htlc.load()?.src_receiver == src_receiver.key() @ HTLCError::NotReciever,
+        constraint = htlc.load()?.refunded == 0 @ HTLCError::AlreadyRefunded,
+    )]
+    pub htlc: AccountLoader<'info, HTLC>,
+    #[account(
+        init,
+        payer = src_receiver,
+        space = size_of::<Counterparty>() + 8,
+        seeds = [
+            b"counterparty".as_ref(),
+            Id.as_ref()
+        ],
+        bump,
+    )]
+    pub counterparty: Account<'info, Counterparty>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct Migrate<'info> {
+    #[account(mut)]
+    payer: Signer<'info>,
+    /// Only loaded once rewritten, the HTLC may still be stored in an old layout.
+    #[account(
+        mut,
+        seeds = [
+            Id.as_ref()
+        ],
+        bump,
+    )]
+    pub htlc: AccountLoader<'info, HTLC>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = size_of::<Config>() + 8,
+        seeds = [b"config".as_ref()],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::NativeHtlc>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(authority.key()) @ HTLCError::UnauthorizedAccess)]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetAdmin<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config".as_ref()],
+        bump = config.bump,
+        has_one = admin @ HTLCError::UnauthorizedAccess,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct SetLimits<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config".as_ref()],
+        bump = config.bump,
+        has_one = admin @ HTLCError::UnauthorizedAccess,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct SetAttesters<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config".as_ref()],
+        bump = config.bump,
+        has_one = admin @ HTLCError::UnauthorizedAccess,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct InitCompressedTree<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    ///CHECK: Created beforehand and owned by spl-account-compression, which
+    /// initializes it.
+    #[account(mut, owner = account_compression::ID)]
+    pub merkle_tree: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = payer,
+        space = size_of::<CompressedTree>() + 8,
+        seeds = [
+            b"compressed_tree".as_ref(),
+            merkle_tree.key().as_ref()
+        ],
+        bump,
+    )]
+    pub compressed_tree: Account<'info, CompressedTree>,
+    ///CHECK: spl-account-compression.
+    #[account(address = account_compression::ID)]
+    pub compression_program: UncheckedAccount<'info>,
+    ///CHECK: The noop program the tree changes are logged through.
+    #[account(address = noop::ID)]
+    pub noop_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> InitCompressedTree<'info> {
+    fn compressed(&self) -> CompressedAccounts<'_, 'info> {
+        CompressedAccounts {
+            compressed_tree: &self.compressed_tree,
+            merkle_tree: &self.merkle_tree,
+            noop_program: &self.noop_program,
+            compression_program: &self.compression_program,
+        }
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32], hashlock: [u8; 32])]
+pub struct LockCompressed<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    ///CHECK: The tree account, checked against the compressed tree.
+    #[account(mut)]
+    pub merkle_tree: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"compressed_tree".as_ref(),
+            merkle_tree.key().as_ref()
+        ],
+        bump = compressed_tree.bump,
+    )]
+    pub compressed_tree: Box<Account<'info, CompressedTree>>,
+    pub dst_chain_entry: Box<Account<'info, ChainEntry>>,
+    pub dst_asset_entry: Box<Account<'info, AssetEntry>>,
+    pub src_asset_entry: Box<Account<'info, AssetEntry>>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = size_of::<SenderStats>() + 8,
+        seeds = [
+            b"sender_stats".as_ref(),
+            sender.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub sender_stats: Box<Account<'info, SenderStats>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = size_of::<HashlockIndex>() + 8,
+        seeds = [
+            b"hashlock_index".as_ref(),
+            sender.key().as_ref(),
+            hashlock.as_ref()
+        ],
+        bump,
+        constraint = hashlock_index.id == [0u8; 32] @ HTLCError::HashlockAlreadyUsed,
+    )]
+    pub hashlock_index: Box<Account<'info, HashlockIndex>>,
+    ///CHECK: spl-account-compression.
+    #[account(address = account_compression::ID)]
+    pub compression_program: UncheckedAccount<'info>,
+    ///CHECK: The noop program the tree changes are logged through.
+    #[account(address = noop::ID)]
+    pub noop_program: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> LockCompressed<'info> {
+    fn compressed(&self) -> CompressedAccounts<'_, 'info> {
+        CompressedAccounts {
+            compressed_tree: &self.compressed_tree,
+            merkle_tree: &self.merkle_tree,
+            noop_program: &self.noop_program,
+            compression_program: &self.compression_program,
+        }
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(htlc: CompressedHtlc)]
+pub struct RedeemCompressed<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+    ///CHECK: The reciever, checked against the leaf.
+    #[account(mut)]
+    pub src_receiver: UncheckedAccount<'info>,
+    ///CHECK: The tree account, checked against the compressed tree.
+    #[account(mut)]
+    pub merkle_tree: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"compressed_tree".as_ref(),
+            merkle_tree.key().as_ref()
+        ],
+        bump = compressed_tree.bump,
+    )]
+    pub compressed_tree: Box<Account<'info, CompressedTree>>,
+    #[account(
+        mut,
+        seeds = [
+            b"sender_stats".as_ref(),
+            htlc.sender.as_ref(),
+        ],
+        bump,
+    )]
+    pub sender_stats: Box<Account<'info, SenderStats>>,
+    ///CHECK: spl-account-compression.
+    #[account(address = account_compression::ID)]
+    pub compression_program: UncheckedAccount<'info>,
+    ///CHECK: The noop program the tree changes are logged through.
+    #[account(address = noop::ID)]
+    pub noop_program: UncheckedAccount<'info>,
+}
+
+impl<'info> RedeemCompressed<'info> {
+    fn compressed(&self) -> CompressedAccounts<'_, 'info> {
+        CompressedAccounts {
+            compressed_tree: &self.compressed_tree,
+            merkle_tree: &self.merkle_tree,
+            noop_program: &self.noop_program,
+            compression_program: &self.compression_program,
+        }
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(htlc: CompressedHtlc)]
+pub struct RefundCompressed<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+    ///CHECK: The refund destination, checked against the leaf.
+    #[account(mut)]
+    pub refund_to: UncheckedAccount<'info>,
+    ///CHECK: The tree account, checked against the compressed tree.
+    #[account(mut)]
+    pub merkle_tree: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"compressed_tree".as_ref(),
+            merkle_tree.key().as_ref()
+        ],
+        bump = compressed_tree.bump,
+    )]
+    pub compressed_tree: Box<Account<'info, CompressedTree>>,
+    #[account(
+        mut,
+        seeds = [
+            b"sender_stats".as_ref(),
+            htlc.sender.as_ref(),
+        ],
+        bump,
+    )]
+    pub sender_stats: Box<Account<'info, SenderStats>>,
+    ///CHECK: spl-account-compression.
+    #[account(address = account_compression::ID)]
+    pub compression_program: UncheckedAccount<'info>,
+    ///CHECK: The noop program the tree changes are logged through.
+    #[account(address = noop::ID)]
+    pub noop_program: UncheckedAccount<'info>,
+}
+
+impl<'info> RefundCompressed<'info> {
+    fn compressed(&self) -> CompressedAccounts<'_, 'info> {
+        CompressedAccounts {
+            compressed_tree: &self.compressed_tree,
+            merkle_tree: &self.merkle_tree,
+            noop_program: &self.noop_program,
+            compression_program: &self.compression_program,
+        }
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(chain_id: String)]
+pub struct SetChain<'info> {
+    pub admin: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump = config.bump,
+        has_one = admin @ HTLCError::UnauthorizedAccess,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = size_of::<ChainEntry>() + 8,
+        seeds = [
+            b"chain".as_ref(),
+            registry_key(&chain_id).as_ref()
+        ],
+        bump,
+    )]
+    pub chain_entry: Account<'info, ChainEntry>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(asset_id: String)]
+pub struct SetAsset<'info> {
+    pub admin: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump = config.bump,
+        has_one = admin @ HTLCError::UnauthorizedAccess,
+    )]
+    pub config: Account<'info, Config>,
+    /// The chain of the asset.
+    pub chain_entry: Account<'info, ChainEntry>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = size_of::<AssetEntry>() + 8,
+        seeds = [
+            b"asset".as_ref(),
+            registry_key(&asset_id).as_ref()
+        ],
+        bump,
+    )]
+    pub asset_entry: Account<'info, AssetEntry>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetDetailsBatch<'info> {
+    pub clock: Sysvar<'info, Clock>,
+}
+
 #[derive(Accounts)]
 #[instruction(Id: [u8; 32])]
 pub struct GetDetails<'info> {
@@ -408,7 +2637,81 @@ pub struct GetDetails<'info> {
         ],
         bump,
     )]
-    pub htlc: Box<Account<'info, HTLC>>,
+    pub htlc: AccountLoader<'info, HTLC>,
+}
+
+#[event]
+pub struct Committed {
+    pub Id: [u8; 32],
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub amount: u64,
+    pub timelock: u64,
+    pub metadata: Vec<u8>,
+}
+
+#[event]
+pub struct Locked {
+    pub Id: [u8; 32],
+    pub hashlock: [u8; 32],
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub amount: u64,
+    pub timelock: u64,
+    pub metadata: Vec<u8>,
+}
+
+#[event]
+pub struct ToppedUp {
+    pub Id: [u8; 32],
+    pub amount: u64,
+    pub total: u64,
+}
+
+#[event]
+pub struct CompressedLocked {
+    pub merkle_tree: Pubkey,
+    pub index: u32,
+    pub htlc: CompressedHtlc,
+    pub dst_chain: String,
+    pub dst_address: String,
+    pub dst_asset: String,
+    pub src_asset: String,
+    pub metadata: Vec<u8>,
+}
+
+/// The new contents of a compressed HTLC leaf after a redeem or refund.
+#[event]
+pub struct CompressedUpdated {
+    pub merkle_tree: Pubkey,
+    pub index: u32,
+    pub htlc: CompressedHtlc,
+}
+
+#[event]
+pub struct ChainSet {
+    pub chain_id: String,
+    pub family: ChainFamily,
+    pub enabled: bool,
+}
+
+#[event]
+pub struct AssetSet {
+    pub asset_id: String,
+    pub enabled: bool,
+}
+
+#[event]
+pub struct CounterpartyLinked {
+    pub Id: [u8; 32],
+    pub counterparty_id: [u8; 32],
+    pub lock_tx_hash: Vec<u8>,
+}
+
+#[event]
+pub struct AttestersSet {
+    pub attesters: Vec<Attester>,
+    pub threshold: u8,
 }
 
 #[error_code]
@@ -437,4 +2740,71 @@ pub enum HTLCError {
     NotSender,
     #[msg("Not The Reciever.")]
     NotReciever,
+    // 12 is `NoToken` of `anchor_htlc`.
+    #[msg("Hashlock Already Used.")]
+    HashlockAlreadyUsed = 13,
+    #[msg("Unknown Account Layout.")]
+    UnknownLayout,
+    #[msg("Already Migrated.")]
+    AlreadyMigrated,
+    #[msg("Field Too Long.")]
+    FieldTooLong,
+    #[msg("Batch Too Large.")]
+    BatchTooLarge,
+    #[msg("No EVM Signer.")]
+    NoEvmSigner,
+    #[msg("Invalid Signature.")]
+    InvalidSignature,
+    #[msg("Not Refund Destination.")]
+    NotRefundTo,
+    #[msg("Invalid Split.")]
+    InvalidSplit,
+    #[msg("Not Split Receiver.")]
+    NotSplitReceiver,
+    #[msg("Invalid Commit Deadline.")]
+    InvalidCommitDeadline,
+    #[msg("Commit Deadline Passed.")]
+    CommitDeadlinePassed,
+    #[msg("Too Many Open HTLCs.")]
+    TooManyOpenHtlcs,
+    #[msg("Locked Limit Exceeded.")]
+    LockedLimitExceeded,
+    #[msg("Sender Stats Required.")]
+    SenderStatsRequired,
+    #[msg("Invalid CAIP Id.")]
+    InvalidCaipId,
+    #[msg("Chain Not Registered.")]
+    ChainNotRegistered,
+    #[msg("Asset Not Registered.")]
+    AssetNotRegistered,
+    #[msg("Invalid Destination Address.")]
+    InvalidDestinationAddress,
+    #[msg("Invalid Counterparty.")]
+    InvalidCounterparty,
+    #[msg("Invalid Attesters.")]
+    InvalidAttesters,
+    #[msg("Attestation Disabled.")]
+    AttestationDisabled,
+    #[msg("Attestation Quorum Not Met.")]
+    AttestationQuorumNotMet,
+    #[msg("Amount Overflow.")]
+    AmountOverflow,
+}
+
+impl From<htlc_core::Error> for HTLCError {
+    fn from(error: htlc_core::Error) -> Self {
+        match error {
+            htlc_core::Error::NotFutureTimeLock => HTLCError::NotFutureTimeLock,
+            htlc_core::Error::NotPastTimeLock => HTLCError::NotPastTimeLock,
+            htlc_core::Error::HashlockNotSet => HTLCError::HashlockNotSet,
+            htlc_core::Error::HashlockNoMatch => HTLCError::HashlockNoMatch,
+            htlc_core::Error::HashlockAlreadySet => HTLCError::HashlockAlreadySet,
+            htlc_core::Error::AlreadyRedeemed => HTLCError::AlreadyRedeemed,
+            htlc_core::Error::AlreadyRefunded => HTLCError::AlreadyRefunded,
+            htlc_core::Error::InvalidCommitDeadline => HTLCError::InvalidCommitDeadline,
+            htlc_core::Error::CommitDeadlinePassed => HTLCError::CommitDeadlinePassed,
+            htlc_core::Error::AttestationDisabled => HTLCError::AttestationDisabled,
+            htlc_core::Error::AttestationQuorumNotMet => HTLCError::AttestationQuorumNotMet,
+        }
+    }
 }
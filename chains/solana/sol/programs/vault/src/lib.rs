@@ -0,0 +1,193 @@
+use anchor_lang::prelude::*;
+use sol::cpi::accounts::{Lock, Refund};
+use sol::program::NativeHtlc;
+use sol::{TimelockKind, HTLC};
+use std::mem::size_of;
+declare_id!("A2pXkTVDBET5dEdgpNcX6P2QkePEN8Ppy3hngakYUjZC");
+/// @title Example vault that swaps its SOL through native_htlc.
+///
+/// The vault is a PDA that carries data, so it can't be the source of a system
+/// transfer. It funds an HTLC by crediting the lamports to the HTLC account
+/// itself and then signs native_htlc::lock as the sender through CPI.
+/// Refunds of expired HTLCs are paid back into the vault.
+#[program]
+pub mod htlc_vault {
+    use super::*;
+
+    /// @dev Creates the vault of the signing authority. Anyone can fund it
+    /// with a system transfer.
+    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.authority = ctx.accounts.authority.key();
+        vault.bump = ctx.bumps.vault;
+        Ok(())
+    }
+
+    /// @dev Called by the authority to lock lamports of the vault in a new HTLC.
+    /// The arguments are passed on to native_htlc::lock.
+    pub fn lock(
+        ctx: Context<LockHtlc>,
+        Id: [u8; 32],
+        hashlock: [u8; 32],
+        timelock: u64,
+        amount: u64,
+        dst_chain: String,
+        dst_address: String,
+        dst_asset: String,
+        src_asset: String,
+        src_receiver: Pubkey,
+        htlc_bump: u8,
+    ) -> Result<[u8; 32]> {
+        // The HTLC account is credited with its rent exemption and the amount,
+        // so native_htlc doesn't transfer them from the vault, see sol::is_prefunded.
+        let funds = Rent::get()?.minimum_balance(HTLC::SPACE) + amount;
+        ctx.accounts.vault.sub_lamports(funds)?;
+        ctx.accounts.htlc.add_lamports(funds)?;
+
+        let authority = ctx.accounts.authority.key();
+        let bump = [ctx.accounts.vault.bump];
+        let seeds = [b"vault".as_ref(), authority.as_ref(), bump.as_ref()];
+        let signer_seeds = [seeds.as_slice()];
+        let lock_context = CpiContext::new_with_signer(
+            ctx.accounts.htlc_program.to_account_info(),
+            Lock {
+                sender: ctx.accounts.vault.to_account_info(),
+                payer: ctx.accounts.authority.to_account_info(),
+                htlc: ctx.accounts.htlc.to_account_info(),
+                hashlock_index: ctx.accounts.hashlock_index.to_account_info(),
+                dst_chain_entry: ctx.accounts.dst_chain_entry.to_account_info(),
+                dst_asset_entry: ctx.accounts.dst_asset_entry.to_account_info(),
+                src_asset_entry: ctx.accounts.src_asset_entry.to_account_info(),
+                config: ctx.accounts.config.to_account_info(),
+                sender_stats: ctx.accounts.sender_stats.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+            },
+            &signer_seeds,
+        );
+        sol::cpi::lock(
+            lock_context,
+            Id,
+            hashlock,
+            timelock,
+            TimelockKind::Timestamp,
+            amount,
+            dst_chain,
+            dst_address,
+            dst_asset,
+            src_asset,
+            src_receiver,
+            None,
+            Vec::new(),
+            Vec::new(),
+            htlc_bump,
+        )
+        .map(|result| result.get())
+    }
+
+    /// @dev Refunds an expired HTLC of the vault back into the vault.
+    pub fn refund(ctx: Context<RefundHtlc>, Id: [u8; 32]) -> Result<bool> {
+        let refund_context = CpiContext::new(
+            ctx.accounts.htlc_program.to_account_info(),
+            Refund {
+                user_signing: ctx.accounts.authority.to_account_info(),
+                htlc: ctx.accounts.htlc.to_account_info(),
+                sender: ctx.accounts.vault.to_account_info(),
+                refund_to: ctx.accounts.vault.to_account_info(),
+                sender_stats: Some(ctx.accounts.sender_stats.to_account_info()),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+            },
+        );
+        sol::cpi::refund(refund_context, Id).map(|result| result.get())
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct Vault {
+    pub authority: Pubkey,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = size_of::<Vault>() + 8,
+        seeds = [
+            b"vault".as_ref(),
+            authority.key().as_ref()
+        ],
+        bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LockHtlc<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            authority.key().as_ref()
+        ],
+        bump = vault.bump,
+        has_one = authority,
+    )]
+    pub vault: Account<'info, Vault>,
+    ///CHECK: Created and checked by native_htlc::lock.
+    #[account(mut)]
+    pub htlc: UncheckedAccount<'info>,
+    ///CHECK: Created and checked by native_htlc::lock.
+    #[account(mut)]
+    pub hashlock_index: UncheckedAccount<'info>,
+    ///CHECK: Checked by native_htlc::lock.
+    pub dst_chain_entry: UncheckedAccount<'info>,
+    ///CHECK: Checked by native_htlc::lock.
+    pub dst_asset_entry: UncheckedAccount<'info>,
+    ///CHECK: Checked by native_htlc::lock.
+    pub src_asset_entry: UncheckedAccount<'info>,
+    ///CHECK: Checked by native_htlc::lock.
+    pub config: UncheckedAccount<'info>,
+    ///CHECK: Created and checked by native_htlc::lock.
+    #[account(mut)]
+    pub sender_stats: UncheckedAccount<'info>,
+
+    pub htlc_program: Program<'info, NativeHtlc>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RefundHtlc<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            authority.key().as_ref()
+        ],
+        bump = vault.bump,
+        has_one = authority,
+    )]
+    pub vault: Account<'info, Vault>,
+    ///CHECK: Checked by native_htlc::refund.
+    #[account(mut)]
+    pub htlc: UncheckedAccount<'info>,
+    ///CHECK: Checked by native_htlc::refund.
+    #[account(mut)]
+    pub sender_stats: UncheckedAccount<'info>,
+
+    pub htlc_program: Program<'info, NativeHtlc>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
@@ -3,7 +3,15 @@ import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { Keypair, PublicKey } from "@solana/web3.js";
 import { randomBytes, createHash } from "crypto";
+import { secp256k1 } from "@noble/curves/secp256k1";
+import { keccak_256 } from "@noble/hashes/sha3";
 import bs58 from 'bs58';
+import {
+  ConcurrentMerkleTreeAccount,
+  getConcurrentMerkleTreeAccountSize,
+  SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+  SPL_NOOP_PROGRAM_ID,
+} from "@solana/spl-account-compression";
 import { NativeHtlc } from '../target/types/native_htlc';
 interface HTLCParameters {
   htlc: anchor.web3.PublicKey;
@@ -23,10 +31,11 @@ describe("HTLC", () => {
   const SECRETArray: number[] = Array.from(SECRET);
   const HASHLOCKArray: number[] = Array.from(HASHLOCK);
   const AMOUNT = 0.01 * anchor.web3.LAMPORTS_PER_SOL;
-  const DSTCHAIN = "ETHEREUM_SEPOLIA";
-  const DSTASSET = "ETH";
-  const SRCASSET = "SOL";
-  const DSTADDRESS = "0x021b6a2ff227f1c71cc6536e7b9e8ecd0d5599b3a934279011e2f2b923d3a782";
+  const DSTCHAIN = "eip155:11155111";
+  const DSTASSET = "eip155:11155111/slip44:60";
+  const SRCCHAIN = "solana:EtWTRABZaYq6iMfeYKouRu166VU2xqa1";
+  const SRCASSET = "solana:EtWTRABZaYq6iMfeYKouRu166VU2xqa1/slip44:501";
+  const DSTADDRESS = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
   const HOPCHAINS = [DSTCHAIN];
   const HOPASSETS = [DSTASSET];
   const HOPADDRESSES = [DSTADDRESS];
@@ -56,6 +65,17 @@ describe("HTLC", () => {
     };
   };
 
+  const senderStats = (sender: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync([Buffer.from("sender_stats"), sender.toBuffer()], program.programId)[0];
+
+  const registryEntry = (kind: string, id: string): PublicKey =>
+    PublicKey.findProgramAddressSync([Buffer.from(kind), createHash("sha256").update(id).digest()], program.programId)[0];
+  const ROUTE = {
+    dstChainEntry: registryEntry("chain", DSTCHAIN),
+    dstAssetEntry: registryEntry("asset", DSTASSET),
+    srcAssetEntry: registryEntry("asset", SRCASSET),
+  };
+
   const createUser = async (): Promise<anchor.web3.Keypair> => {
     const user = new anchor.web3.Keypair();
     const userWallet = new anchor.Wallet(user);
@@ -83,6 +103,29 @@ describe("HTLC", () => {
     bob = await createUser();
     pda = await getHTLC(ID);
     signature = await ed.sign(MSG, alice.secretKey.slice(0, 32));
+
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    await program.methods.initializeConfig(wallet.publicKey)
+      .accountsPartial({ authority: wallet.publicKey, programData })
+      .rpc();
+    for (const [chain, family] of [[DSTCHAIN, { evm: {} }], [SRCCHAIN, { solana: {} }]] as [string, any][]) {
+      await program.methods.setChain(chain, family, true)
+        .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, chainEntry: registryEntry("chain", chain) })
+        .rpc();
+    }
+    for (const [chain, asset] of [[DSTCHAIN, DSTASSET], [SRCCHAIN, SRCASSET]]) {
+      await program.methods.setAsset(asset, true)
+        .accountsPartial({
+          admin: wallet.publicKey,
+          payer: wallet.publicKey,
+          chainEntry: registryEntry("chain", chain),
+          assetEntry: registryEntry("asset", asset),
+        })
+        .rpc();
+    }
   });
   it("create prehtlc", async () => {
 
@@ -91,9 +134,11 @@ describe("HTLC", () => {
     console.log("lamport balance of wallet before commit",
       await anchor.getProvider().connection.getBalance(wallet.publicKey));
     const commitTx = await program.methods
-      .commit(IDArray, HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, wallet.publicKey, TIMELOCK, new anchor.BN(AMOUNT), pda.htlcBump)
+      .commit(IDArray, HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, wallet.publicKey, TIMELOCK, { timestamp: {} }, new anchor.BN(AMOUNT), null, null, null, Buffer.alloc(0), pda.htlcBump)
       .accountsPartial({
+        ...ROUTE,
         sender: user.publicKey,
+        payer: wallet.publicKey,
         htlc: pda.htlc,
       })
       .signers([user.payer])
@@ -104,7 +149,7 @@ describe("HTLC", () => {
     console.log("lamport balance of Alice-wallet before addLock",
       await anchor.getProvider().connection.getBalance(user.publicKey));
 
-    const signAddLock = await program.methods.addLock(IDArray, HASHLOCKArray, TIMELOCK).
+    const signAddLock = await program.methods.addLock(IDArray, HASHLOCKArray, TIMELOCK, []).
       accountsPartial({
         sender: user.publicKey,
         payer: wallet.publicKey,
@@ -140,6 +185,7 @@ describe("HTLC", () => {
         userSigning: user.publicKey,
         htlc: pda.htlc,
         srcReceiver: wallet.publicKey,
+        senderStats: senderStats(user.publicKey),
       })
       .signers([user.payer])
       .rpc();
@@ -172,7 +218,7 @@ describe("HTLC", () => {
   //   console.log("lamport balance of wallet at the beginning",
   //     await anchor.getProvider().connection.getBalance(wallet.publicKey));
   //   const tx1 = await program.methods
-  //     .lock(IDArray, HASHLOCKArray, TIMELOCK, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, pda.htlcBump)
+  //     .lock(IDArray, HASHLOCKArray, TIMELOCK, { timestamp: {} }, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, pda.htlcBump)
   //     .accountsPartial({
   //       sender: wallet.publicKey,
   //       htlc: pda.htlc,
@@ -221,7 +267,7 @@ describe("HTLC", () => {
   //     await anchor.getProvider().connection.getBalance(wallet.publicKey));
 
   //   const lockTx = await program.methods
-  //     .lock(IDArray, HASHLOCKArray, TIMELOCK, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, pda.htlcBump)
+  //     .lock(IDArray, HASHLOCKArray, TIMELOCK, { timestamp: {} }, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, pda.htlcBump)
   //     .accountsPartial({
   //       sender: wallet.publicKey,
   //       htlc: pda.htlc,
@@ -253,4 +299,608 @@ describe("HTLC", () => {
   //     await anchor.getProvider().connection.getBalance(wallet.publicKey));
   // });
 
+  it("migrates an HTLC stored in the pre-versioning layout", async () => {
+    // Loaded into the validator from tests/fixtures/htlc_v0.json (see Anchor.toml).
+    const V0_ID = Buffer.alloc(32, 0x11);
+    const V0_SECRET = Buffer.alloc(32, 0x22);
+    const V0_RECEIVER = new PublicKey(Buffer.alloc(32, 0x44));
+    const v0 = await getHTLC(V0_ID);
+
+    // An HTLC in an older layout is read without migrating it.
+    const stored = await program.methods.getDetails(Array.from(V0_ID)).accountsPartial({ htlc: v0.htlc }).view();
+    assert.equal(stored.version, 0);
+    assert.equal(stored.dstChain, "ETHEREUM_SEPOLIA");
+
+    // It can't be redeemed until it is migrated.
+    await assert.rejects(
+      program.methods.redeem(Array.from(V0_ID), Array.from(V0_SECRET))
+        .accountsPartial({
+          userSigning: wallet.publicKey,
+          htlc: v0.htlc,
+          srcReceiver: V0_RECEIVER,
+        })
+        .rpc(),
+      /UnknownLayout/
+    );
+
+    await program.methods.migrate(Array.from(V0_ID))
+      .accountsPartial({
+        payer: wallet.publicKey,
+        htlc: v0.htlc,
+      })
+      .rpc();
+
+    const info = await provider.connection.getAccountInfo(v0.htlc);
+    assert.equal(info.data.length, 824);
+    const details = await program.methods.getDetails(Array.from(V0_ID)).accountsPartial({ htlc: v0.htlc }).view();
+    assert.equal(details.version, 1);
+    assert.equal(details.dstChain, "ETHEREUM_SEPOLIA");
+    assert.equal(details.srcReceiver.toBase58(), V0_RECEIVER.toBase58());
+    assert.equal(details.amount.toNumber(), 10000000);
+    assert.deepEqual(details.timelockKind, { timestamp: {} });
+    assert.equal(details.refundTo.toBase58(), details.sender.toBase58());
+
+    await assert.rejects(
+      program.methods.migrate(Array.from(V0_ID))
+        .accountsPartial({
+          payer: wallet.publicKey,
+          htlc: v0.htlc,
+        })
+        .rpc(),
+      /AlreadyMigrated/
+    );
+
+    await program.methods.redeem(Array.from(V0_ID), Array.from(V0_SECRET))
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc: v0.htlc,
+        srcReceiver: V0_RECEIVER,
+      })
+      .rpc();
+    assert.equal(await provider.connection.getBalance(V0_RECEIVER), 10000000);
+  });
+
+  it("reports the compute units used by lock and redeem", async () => {
+    const id = randomBytes(32);
+    const secret = randomBytes(32);
+    const hashlock = createHash("sha256").update(secret).digest();
+    const htlc = await getHTLC(id);
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    const unitsConsumed = async (signature: string) => {
+      const tx = await provider.connection.getTransaction(signature, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
+      return tx.meta.computeUnitsConsumed;
+    };
+
+    const lockTx = await program.methods
+      .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, null, [], Buffer.alloc(0), htlc.htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc: htlc.htlc,
+      })
+      .rpc({ commitment: "confirmed" });
+    const redeemTx = await program.methods.redeem(Array.from(id), Array.from(secret))
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc: htlc.htlc,
+        srcReceiver: bob.publicKey,
+        senderStats: senderStats(wallet.publicKey),
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("compute units: lock", await unitsConsumed(lockTx), "redeem", await unitsConsumed(redeemTx));
+  });
+
+  it("adds the lock with a signature of the sender's EVM key", async () => {
+    const id = randomBytes(32);
+    const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+    const htlc = await getHTLC(id);
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    const evmKey = secp256k1.utils.randomPrivateKey();
+    const evmPublicKey = secp256k1.getPublicKey(evmKey, false).slice(1);
+    // EIP-712 digest of addLockMsg(bytes32 Id,bytes32 hashlock,uint256 timelock), see add_lock_digest.
+    const addLockDigest = (lockTimelock: anchor.BN) => {
+      const domainSeparator = keccak_256(Buffer.concat([
+        keccak_256("EIP712Domain(string name,string version,bytes32 salt)"),
+        keccak_256("LayerswapV8"),
+        keccak_256("1"),
+        program.programId.toBuffer(),
+      ]));
+      const structHash = keccak_256(Buffer.concat([
+        keccak_256("addLockMsg(bytes32 Id,bytes32 hashlock,uint256 timelock)"),
+        id,
+        hashlock,
+        lockTimelock.toArrayLike(Buffer, "be", 32),
+      ]));
+      return keccak_256(Buffer.concat([Buffer.from([0x19, 0x01]), domainSeparator, structHash]));
+    };
+    const addLockSig = (signedTimelock: anchor.BN) => {
+      const signature = secp256k1.sign(addLockDigest(signedTimelock), evmKey);
+      return program.methods
+        .addLockSig(Array.from(id), Array.from(hashlock), timelock, { typedData: {} }, Array.from(signature.toCompactRawBytes()), signature.recovery)
+        .accountsPartial({
+          payer: wallet.publicKey,
+          htlc: htlc.htlc,
+          hashlockIndex: PublicKey.findProgramAddressSync([Buffer.from("hashlock_index"), wallet.publicKey.toBuffer(), hashlock], program.programId)[0],
+        })
+        .rpc();
+    };
+
+    await program.methods
+      .commit(Array.from(id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, { timestamp: {} }, new anchor.BN(AMOUNT), { publicKey: [Array.from(evmPublicKey)] }, null, null, Buffer.alloc(0), htlc.htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc: htlc.htlc,
+      })
+      .rpc();
+
+    await assert.rejects(addLockSig(timelock.addn(1)), /InvalidSignature/);
+    await addLockSig(timelock);
+
+    const details = await program.methods.getDetails(Array.from(id)).accountsPartial({ htlc: htlc.htlc }).view();
+    assert.deepEqual(details.hashlock, Array.from(hashlock));
+  });
+
+  it("reports many HTLCs with one getDetailsBatch call", async () => {
+    const v0 = await getHTLC(Buffer.alloc(32, 0x11));
+    const missing = await getHTLC(randomBytes(32));
+    const summaries = await program.methods.getDetailsBatch()
+      .remainingAccounts([v0.htlc, missing.htlc].map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })))
+      .view();
+    assert.deepEqual(summaries.map((summary) => summary.status), [{ redeemed: {} }, { missing: {} }]);
+    assert.equal(summaries[0].amount.toNumber(), 10000000);
+  });
+
+
+  it("refunds to the refund_to address set at lock", async () => {
+    const id = randomBytes(32);
+    const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+    const htlc = await getHTLC(id);
+    const coldWallet = new Keypair();
+    const timelock = new anchor.BN(Math.floor(Date.now() / 1000) + 2);
+
+    await program.methods
+      .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, coldWallet.publicKey, [], Buffer.alloc(0), htlc.htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc: htlc.htlc,
+      })
+      .rpc();
+
+    await wait(5000);
+    await assert.rejects(
+      program.methods.refund(Array.from(id))
+        .accountsPartial({
+          userSigning: wallet.publicKey,
+          htlc: htlc.htlc,
+          sender: wallet.publicKey,
+          refundTo: wallet.publicKey,
+          senderStats: senderStats(wallet.publicKey),
+        })
+        .rpc(),
+      /NotRefundTo/
+    );
+    await program.methods.refund(Array.from(id))
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc: htlc.htlc,
+        sender: wallet.publicKey,
+        refundTo: coldWallet.publicKey,
+        senderStats: senderStats(wallet.publicKey),
+      })
+      .rpc();
+    assert.equal(await provider.connection.getBalance(coldWallet.publicKey), AMOUNT);
+  });
+
+  it("splits the redeemed amount between the receivers and keeps the metadata", async () => {
+    const id = randomBytes(32);
+    const secret = randomBytes(32);
+    const hashlock = createHash("sha256").update(secret).digest();
+    const htlc = await getHTLC(id);
+    const lp = new Keypair();
+    const partner = new Keypair();
+    const primary = new Keypair();
+    const amount = 10000003;
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    const splits = [
+      { receiver: lp.publicKey, share: 2500 },
+      { receiver: partner.publicKey, share: 1000 },
+    ];
+    // Opaque to the program, e.g. the ids of the order and quote behind the swap.
+    const METADATA = Buffer.from("order:42/quote:7");
+
+    await program.methods
+      .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, new anchor.BN(amount), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, primary.publicKey, null, splits, METADATA, htlc.htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc: htlc.htlc,
+      })
+      .rpc();
+
+    await program.methods.redeem(Array.from(id), Array.from(secret))
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc: htlc.htlc,
+        srcReceiver: primary.publicKey,
+        senderStats: senderStats(wallet.publicKey),
+      })
+      .remainingAccounts(splits.map((split) => ({ pubkey: split.receiver, isSigner: false, isWritable: true })))
+      .rpc();
+
+    assert.equal(await provider.connection.getBalance(lp.publicKey), 2500000);
+    assert.equal(await provider.connection.getBalance(partner.publicKey), 1000000);
+    // The primary receiver gets the rest, including the rounding dust.
+    assert.equal(await provider.connection.getBalance(primary.publicKey), 6500003);
+    const details = await program.methods.getDetails(Array.from(id)).accountsPartial({ htlc: htlc.htlc }).view();
+    assert.deepEqual(Buffer.from(details.metadata), METADATA);
+  });
+
+  it("refunds a commit without a hashlock early after the commit deadline", async () => {
+    const id = randomBytes(32);
+    const htlc = await getHTLC(id);
+    const now = Math.floor(Date.now() / 1000);
+    const timelock = new anchor.BN(now + 3600);
+    const commitDeadline = new anchor.BN(now + 2);
+
+    await program.methods
+      .commit(Array.from(id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, { timestamp: {} }, new anchor.BN(AMOUNT), null, null, commitDeadline, Buffer.alloc(0), htlc.htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc: htlc.htlc,
+      })
+      .rpc();
+
+    await wait(5000);
+    const [summary] = await program.methods.getDetailsBatch()
+      .remainingAccounts([{ pubkey: htlc.htlc, isSigner: false, isWritable: false }])
+      .view();
+    assert.deepEqual(summary.status, { expired: {} });
+
+    await program.methods.refund(Array.from(id))
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc: htlc.htlc,
+        sender: wallet.publicKey,
+        refundTo: wallet.publicKey,
+        senderStats: senderStats(wallet.publicKey),
+      })
+      .rpc();
+    const details = await program.methods.getDetails(Array.from(id)).accountsPartial({ htlc: htlc.htlc }).view();
+    assert.equal(details.refunded, true);
+  });
+
+  it("caps the open HTLCs of a sender", async () => {
+    const lockAs = async (sender: Keypair) => {
+      const id = randomBytes(32);
+      const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+      const htlc = await getHTLC(id);
+      const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+      return program.methods
+        .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, null, [], Buffer.alloc(0), htlc.htlcBump)
+        .accountsPartial({
+          ...ROUTE,
+          sender: sender.publicKey,
+          payer: wallet.publicKey,
+          htlc: htlc.htlc,
+        })
+        .signers([sender])
+        .rpc();
+    };
+
+    const sender = await createUser();
+    await program.methods.setLimits(1, new anchor.BN(0)).accountsPartial({ admin: wallet.publicKey }).rpc();
+    await lockAs(sender);
+    await assert.rejects(lockAs(sender), /TooManyOpenHtlcs/);
+    await program.methods.setLimits(0, new anchor.BN(0)).accountsPartial({ admin: wallet.publicKey }).rpc();
+
+    const stats = await program.account.senderStats.fetch(senderStats(sender.publicKey));
+    assert.equal(stats.openHtlcs, 1);
+    assert.equal(stats.locked.toNumber(), AMOUNT);
+  });
+
+  it("only locks routes of enabled chains and assets of the registry to valid addresses", async () => {
+    const lockWith = async (dstAsset: string, route: typeof ROUTE, dstAddress = DSTADDRESS) => {
+      const id = randomBytes(32);
+      const htlc = await getHTLC(id);
+      const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+      const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+      return program.methods
+        .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, new anchor.BN(AMOUNT), DSTCHAIN, dstAddress, dstAsset, SRCASSET, bob.publicKey, null, [], Buffer.alloc(0), htlc.htlcBump)
+        .accountsPartial({ ...route, sender: wallet.publicKey, payer: wallet.publicKey, htlc: htlc.htlc })
+        .rpc();
+    };
+
+    // The source asset is registered, but not on the destination chain.
+    await assert.rejects(lockWith(SRCASSET, { ...ROUTE, dstAssetEntry: ROUTE.srcAssetEntry }), /AssetNotRegistered/);
+    await assert.rejects(program.methods.setChain("ETHEREUM_SEPOLIA", { evm: {} }, true)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, chainEntry: registryEntry("chain", "ETHEREUM_SEPOLIA") })
+      .rpc(), /InvalidCaipId/);
+
+    // The last letter has the wrong case for the EIP-55 checksum.
+    await assert.rejects(lockWith(DSTASSET, ROUTE, DSTADDRESS.slice(0, -1) + "D"), /InvalidDestinationAddress/);
+
+    const setDstChain = (enabled: boolean) => program.methods.setChain(DSTCHAIN, { evm: {} }, enabled)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, chainEntry: ROUTE.dstChainEntry })
+      .rpc();
+    await setDstChain(false);
+    await assert.rejects(lockWith(DSTASSET, ROUTE), /ChainNotRegistered/);
+    await setDstChain(true);
+  });
+
+  it("links an HTLC to its counterparty on the destination chain", async () => {
+    const id = randomBytes(32);
+    const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+    const htlc = await getHTLC(id);
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    await program.methods
+      .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, null, [], Buffer.alloc(0), htlc.htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc: htlc.htlc,
+      })
+      .rpc();
+
+    const counterpartyId = randomBytes(32);
+    const lockTxHash = randomBytes(32);
+    const link = () => program.methods
+      .linkCounterparty(Array.from(id), Array.from(counterpartyId), lockTxHash)
+      .accountsPartial({ srcReceiver: bob.publicKey, htlc: htlc.htlc })
+      .signers([bob])
+      .rpc();
+    await link();
+    await assert.rejects(link());
+
+    const [counterparty] = PublicKey.findProgramAddressSync([Buffer.from("counterparty"), id], program.programId);
+    const linked = await program.account.counterparty.fetch(counterparty);
+    assert.deepEqual(linked.counterpartyId, Array.from(counterpartyId));
+    assert.deepEqual(linked.lockTxHash.slice(0, linked.lockTxHashLen), Array.from(lockTxHash));
+  });
+
+  it("redeems an HTLC with the attestation of the guardians instead of the secret", async () => {
+    const id = randomBytes(32);
+    const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+    const htlc = await getHTLC(id);
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    await program.methods
+      .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, null, [], Buffer.alloc(0), htlc.htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc: htlc.htlc,
+      })
+      .rpc();
+
+    // One Ed25519 and one secp256k1 attester, both needed.
+    const guardian = new Keypair();
+    const evmKey = secp256k1.utils.randomPrivateKey();
+    const evmAddress = keccak_256(secp256k1.getPublicKey(evmKey, false).slice(1)).slice(12);
+    const attesters = [
+      { kind: { ed25519: {} }, key: Array.from(guardian.publicKey.toBytes()) },
+      { kind: { secp256k1: {} }, key: [...evmAddress, ...new Array(12).fill(0)] },
+    ];
+    await program.methods.setAttesters(attesters, 2).accountsPartial({ admin: wallet.publicKey }).rpc();
+
+    // See htlc_core::attestation::message.
+    const message = Buffer.concat([Buffer.from("HTLC destination leg completed"), program.programId.toBuffer(), id]);
+    const ed25519 = anchor.web3.Ed25519Program.createInstructionWithPrivateKey({ privateKey: guardian.secretKey, message });
+    const signature = secp256k1.sign(keccak_256(message), evmKey);
+    const evmSignature = { attester: 1, signature: Array.from(signature.toCompactRawBytes()), recoveryId: signature.recovery };
+    const redeemAttested = (signatures: typeof evmSignature[]) => program.methods
+      .redeemAttested(Array.from(id), signatures)
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc: htlc.htlc,
+        srcReceiver: bob.publicKey,
+        senderStats: senderStats(wallet.publicKey),
+      })
+      .preInstructions([ed25519])
+      .rpc();
+
+    await assert.rejects(redeemAttested([]), /AttestationQuorumNotMet/);
+    await redeemAttested([evmSignature]);
+    const details = await program.methods.getDetails(Array.from(id)).accountsPartial({ htlc: htlc.htlc }).view();
+    assert.equal(details.redeemed, true);
+
+    await program.methods.setAttesters([], 0).accountsPartial({ admin: wallet.publicKey }).rpc();
+  });
+
+  it("locks and redeems a compressed HTLC", async () => {
+    const MAX_DEPTH = 3;
+    const MAX_BUFFER_SIZE = 8;
+    const merkleTree = new Keypair();
+    const space = getConcurrentMerkleTreeAccountSize(MAX_DEPTH, MAX_BUFFER_SIZE);
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.createAccount({
+          fromPubkey: wallet.publicKey,
+          newAccountPubkey: merkleTree.publicKey,
+          lamports: await provider.connection.getMinimumBalanceForRentExemption(space),
+          space,
+          programId: SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+        })
+      ),
+      [merkleTree]
+    );
+    const treeAccounts = {
+      merkleTree: merkleTree.publicKey,
+      compressionProgram: SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+      noopProgram: SPL_NOOP_PROGRAM_ID,
+    };
+    await program.methods.initCompressedTree(MAX_DEPTH, MAX_BUFFER_SIZE)
+      .accountsPartial({ payer: wallet.publicKey, ...treeAccounts })
+      .rpc();
+
+    const id = randomBytes(32);
+    const secret = randomBytes(32);
+    const hashlock = createHash("sha256").update(secret).digest();
+    const receiver = new Keypair();
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    await program.methods
+      .lockCompressed(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, receiver.publicKey, null, Buffer.alloc(0))
+      .accountsPartial({ sender: wallet.publicKey, payer: wallet.publicKey, ...ROUTE, ...treeAccounts })
+      .rpc();
+    const [hashlockIndex] = PublicKey.findProgramAddressSync([Buffer.from("hashlock_index"), wallet.publicKey.toBuffer(), hashlock], program.programId);
+    assert.deepEqual((await program.account.hashlockIndex.fetch(hashlockIndex)).id, Array.from(id));
+
+    // The only leaf is at index 0, so its proof consists of the empty subtrees.
+    const proof: PublicKey[] = [];
+    let node = Buffer.alloc(32);
+    for (let level = 0; level < MAX_DEPTH; level++) {
+      proof.push(new PublicKey(node));
+      node = Buffer.from(keccak_256(Buffer.concat([node, node])));
+    }
+    const tree = await ConcurrentMerkleTreeAccount.fromAccountAddress(provider.connection, merkleTree.publicKey);
+    const leaf = {
+      id: Array.from(id),
+      sender: wallet.publicKey,
+      srcReceiver: receiver.publicKey,
+      refundTo: wallet.publicKey,
+      hashlock: Array.from(hashlock),
+      secret: Array.from(ZEROS),
+      amount: new anchor.BN(AMOUNT),
+      timelock,
+      timelockKind: { timestamp: {} },
+      redeemed: false,
+      refunded: false,
+    };
+    const redeem = (htlc: typeof leaf) => program.methods
+      .redeemCompressed(htlc, 0, Array.from(tree.getCurrentRoot()), Array.from(secret))
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        srcReceiver: receiver.publicKey,
+        senderStats: senderStats(wallet.publicKey),
+        ...treeAccounts,
+      })
+      .remainingAccounts(proof.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })))
+      .rpc();
+
+    // A leaf that is not in the tree fails the proof.
+    await assert.rejects(redeem({ ...leaf, amount: new anchor.BN(AMOUNT * 2) }));
+    await redeem(leaf);
+    assert.equal(await provider.connection.getBalance(receiver.publicKey), AMOUNT);
+  });
+
+  it("tops up a committed HTLC within the limits of its sender", async () => {
+    const id = randomBytes(32);
+    const htlc = await getHTLC(id);
+    const sender = await createUser();
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    await program.methods
+      .commit(Array.from(id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, { timestamp: {} }, new anchor.BN(AMOUNT), null, null, null, Buffer.alloc(0), htlc.htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: sender.publicKey,
+        payer: wallet.publicKey,
+        htlc: htlc.htlc,
+      })
+      .signers([sender])
+      .rpc();
+    const topUp = (signer: Keypair, amount: number) => program.methods.topUp(Array.from(id), new anchor.BN(amount))
+      .accountsPartial({
+        sender: signer.publicKey,
+        htlc: htlc.htlc,
+        senderStats: senderStats(sender.publicKey),
+      })
+      .signers([signer])
+      .rpc();
+
+    await topUp(sender, AMOUNT);
+    await assert.rejects(topUp(bob, AMOUNT), /UnauthorizedAccess/);
+    await program.methods.setLimits(0, new anchor.BN(2 * AMOUNT)).accountsPartial({ admin: wallet.publicKey }).rpc();
+    await assert.rejects(topUp(sender, 1), /LockedLimitExceeded/);
+    await program.methods.setLimits(0, new anchor.BN(0)).accountsPartial({ admin: wallet.publicKey }).rpc();
+
+    const details = await program.methods.getDetails(Array.from(id)).accountsPartial({ htlc: htlc.htlc }).view();
+    assert.equal(details.amount.toNumber(), 2 * AMOUNT);
+    const stats = await program.account.senderStats.fetch(senderStats(sender.publicKey));
+    assert.equal(stats.locked.toNumber(), 2 * AMOUNT);
+  });
+
+  it("refunds an HTLC with a slot timelock only after the slot", async () => {
+    const id = randomBytes(32);
+    const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+    const htlc = await getHTLC(id);
+    const timelock = (await provider.connection.getSlot()) + 10;
+    await program.methods
+      .lock(Array.from(id), Array.from(hashlock), new anchor.BN(timelock), { slot: {} }, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, null, [], Buffer.alloc(0), htlc.htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc: htlc.htlc,
+      })
+      .rpc();
+    const refund = () => program.methods.refund(Array.from(id))
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc: htlc.htlc,
+        sender: wallet.publicKey,
+        refundTo: wallet.publicKey,
+        senderStats: senderStats(wallet.publicKey),
+      })
+      .rpc();
+
+    await assert.rejects(refund(), /NotPastTimeLock/);
+    while ((await provider.connection.getSlot()) < timelock) {
+      await wait(400);
+    }
+    await refund();
+    const details = await program.methods.getDetails(Array.from(id)).accountsPartial({ htlc: htlc.htlc }).view();
+    assert.deepEqual(details.timelockKind, { slot: {} });
+    assert.equal(details.refunded, true);
+  });
+
+  it("redeems by hashlock through the hashlock index of the sender", async () => {
+    const secret = randomBytes(32);
+    const hashlock = createHash("sha256").update(secret).digest();
+    const receiver = new Keypair();
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    const lockAs = async (sender: Keypair) => {
+      const id = randomBytes(32);
+      const htlc = await getHTLC(id);
+      await program.methods
+        .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, receiver.publicKey, null, [], Buffer.alloc(0), htlc.htlcBump)
+        .accountsPartial({
+          ...ROUTE,
+          sender: sender.publicKey,
+          payer: wallet.publicKey,
+          htlc: htlc.htlc,
+        })
+        .signers([sender])
+        .rpc();
+      return { id, ...htlc };
+    };
+
+    // Someone else locking the hashlock first doesn't block the sender.
+    await lockAs(await createUser());
+    const htlc = await lockAs(wallet.payer);
+    await assert.rejects(lockAs(wallet.payer), /HashlockAlreadyUsed/);
+
+    const [hashlockIndex] = PublicKey.findProgramAddressSync([Buffer.from("hashlock_index"), wallet.publicKey.toBuffer(), hashlock], program.programId);
+    await program.methods.redeemByHashlock(Array.from(hashlock), Array.from(secret))
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        hashlockIndex,
+        htlc: htlc.htlc,
+        sender: wallet.publicKey,
+        srcReceiver: receiver.publicKey,
+        senderStats: senderStats(wallet.publicKey),
+      })
+      .rpc();
+    assert.equal(await provider.connection.getBalance(receiver.publicKey), AMOUNT);
+    const details = await program.methods.getDetails(Array.from(htlc.id)).accountsPartial({ htlc: htlc.htlc }).view();
+    assert.deepEqual(details.secret, Array.from(secret));
+  });
 });
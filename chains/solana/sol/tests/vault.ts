@@ -0,0 +1,115 @@
+import assert from "assert";
+import * as anchor from "@coral-xyz/anchor";
+import { randomBytes, createHash } from "crypto";
+import { NativeHtlc } from '../target/types/native_htlc';
+import { HtlcVault } from '../target/types/htlc_vault';
+
+describe("HTLC vault", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+  const program = anchor.workspace.NativeHtlc as anchor.Program<NativeHtlc>;
+  const vaultProgram = anchor.workspace.HtlcVault as anchor.Program<HtlcVault>;
+  const wallet = provider.wallet as anchor.Wallet;
+  const AMOUNT = 0.01 * anchor.web3.LAMPORTS_PER_SOL;
+  const DSTCHAIN = "eip155:11155111";
+  const DSTASSET = "eip155:11155111/slip44:60";
+  const SRCCHAIN = "solana:EtWTRABZaYq6iMfeYKouRu166VU2xqa1";
+  const SRCASSET = "solana:EtWTRABZaYq6iMfeYKouRu166VU2xqa1/slip44:501";
+  const DSTADDRESS = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+  const [vault] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("vault"), wallet.publicKey.toBuffer()],
+    vaultProgram.programId
+  );
+  const [config] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId);
+  const [senderStats] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("sender_stats"), vault.toBuffer()],
+    program.programId
+  );
+  const registryEntry = (kind: string, id: string) =>
+    anchor.web3.PublicKey.findProgramAddressSync([Buffer.from(kind), createHash("sha256").update(id).digest()], program.programId)[0];
+
+  function wait(ms: number): Promise<void> {
+    return new Promise((resolve) => setTimeout(resolve, ms));
+  }
+
+  before(async () => {
+    if (await provider.connection.getAccountInfo(config) === null) {
+      const [programData] = anchor.web3.PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        new anchor.web3.PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+      );
+      await program.methods.initializeConfig(wallet.publicKey)
+        .accountsPartial({ authority: wallet.publicKey, programData })
+        .rpc();
+    }
+    // Registering is idempotent, so this doesn't depend on the order of the test files.
+    for (const [chain, family] of [[DSTCHAIN, { evm: {} }], [SRCCHAIN, { solana: {} }]] as [string, any][]) {
+      await program.methods.setChain(chain, family, true)
+        .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, chainEntry: registryEntry("chain", chain) })
+        .rpc();
+    }
+    for (const [chain, asset] of [[DSTCHAIN, DSTASSET], [SRCCHAIN, SRCASSET]]) {
+      await program.methods.setAsset(asset, true)
+        .accountsPartial({
+          admin: wallet.publicKey,
+          payer: wallet.publicKey,
+          chainEntry: registryEntry("chain", chain),
+          assetEntry: registryEntry("asset", asset),
+        })
+        .rpc();
+    }
+  });
+
+  it("locks and refunds the SOL of a PDA through CPI", async () => {
+    await vaultProgram.methods.initialize().accountsPartial({ authority: wallet.publicKey, vault }).rpc();
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: wallet.publicKey,
+          toPubkey: vault,
+          lamports: 0.05 * anchor.web3.LAMPORTS_PER_SOL,
+        })
+      )
+    );
+    const vaultBefore = await provider.connection.getBalance(vault);
+
+    const ID = randomBytes(32);
+    const HASHLOCK = createHash("sha256").update(randomBytes(32)).digest();
+    const [htlc, htlcBump] = anchor.web3.PublicKey.findProgramAddressSync([ID], program.programId);
+    const [hashlockIndex] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("hashlock_index"), vault.toBuffer(), HASHLOCK],
+      program.programId
+    );
+    const TIMELOCK = new anchor.BN(Math.floor(Date.now() / 1000) + 5);
+    await vaultProgram.methods
+      .lock(Array.from(ID), Array.from(HASHLOCK), TIMELOCK, new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, wallet.publicKey, htlcBump)
+      .accountsPartial({
+        authority: wallet.publicKey,
+        vault,
+        htlc,
+        hashlockIndex,
+        dstChainEntry: registryEntry("chain", DSTCHAIN),
+        dstAssetEntry: registryEntry("asset", DSTASSET),
+        srcAssetEntry: registryEntry("asset", SRCASSET),
+        config,
+        senderStats,
+      })
+      .rpc();
+
+    const details = await program.methods.getDetails(Array.from(ID)).accountsPartial({ htlc }).view();
+    assert.equal(details.sender.toBase58(), vault.toBase58());
+    assert.equal(details.amount.toString(), AMOUNT.toString());
+    const htlcSize = (await provider.connection.getAccountInfo(htlc)).data.length;
+
+    await wait(10000);
+    await vaultProgram.methods
+      .refund(Array.from(ID))
+      .accountsPartial({ authority: wallet.publicKey, vault, htlc, senderStats })
+      .rpc();
+
+    // The vault gets the amount back, the rent stays with the HTLC account.
+    const htlcRent = await provider.connection.getMinimumBalanceForRentExemption(htlcSize);
+    assert.equal(await provider.connection.getBalance(vault), vaultBefore - htlcRent);
+  });
+});
@@ -0,0 +1,162 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_hook::TransferHookAccount, BaseStateWithExtensions, StateWithExtensions},
+};
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use spl_tlv_account_resolution::{
+    account::ExtraAccountMeta, seeds::Seed, state::ExtraAccountMetaList,
+};
+use spl_transfer_hook_interface::instruction::ExecuteInstruction;
+use std::mem::size_of;
+declare_id!("GscpbR9m5CFhpRB7BBiqiTQvBQaFC3i7ZWLQwJpLEkf8");
+/// @title Example Token-2022 transfer hook that counts the transfers of a mint.
+///
+/// Every transfer of the mint needs the counter PDA of the mint as an extra
+/// account, so transfers made by anchor_htlc have to pass the accounts of the
+/// hook as remaining accounts.
+#[program]
+pub mod transfer_hook {
+    use super::*;
+
+    /// @dev Creates the extra account metas of the mint, which resolve to the
+    /// counter PDA of the mint, and the counter itself.
+    #[interface(spl_transfer_hook_interface::initialize_extra_account_meta_list)]
+    pub fn initialize_extra_account_meta_list(
+        ctx: Context<InitializeExtraAccountMetaList>,
+    ) -> Result<()> {
+        ExtraAccountMetaList::init::<ExecuteInstruction>(
+            &mut ctx.accounts.extra_account_meta_list.try_borrow_mut_data()?,
+            &extra_account_metas()?,
+        )?;
+        ctx.accounts.counter.bump = ctx.bumps.counter;
+        Ok(())
+    }
+
+    /// @dev Called by the token program on every transfer of the mint. Fails
+    /// when called directly, outside of a transfer.
+    #[interface(spl_transfer_hook_interface::execute)]
+    pub fn transfer_hook(ctx: Context<TransferHook>, amount: u64) -> Result<()> {
+        check_is_transferring(&ctx.accounts.source_token.to_account_info())?;
+        let counter = &mut ctx.accounts.counter;
+        counter.transfers = counter
+            .transfers
+            .checked_add(1)
+            .ok_or(TransferHookError::Overflow)?;
+        counter.amount = counter
+            .amount
+            .checked_add(amount)
+            .ok_or(TransferHookError::Overflow)?;
+        Ok(())
+    }
+}
+
+/// Fails unless the token program is in the middle of a transfer from
+/// `source_token`. Token-2022 sets the `transferring` flag of the source
+/// account while it calls the hook, so anyone calling the hook directly can't
+/// bump the counter.
+fn check_is_transferring(source_token: &AccountInfo) -> Result<()> {
+    let data = source_token.try_borrow_data()?;
+    let account = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+    let extension = account.get_extension::<TransferHookAccount>()?;
+    require!(
+        bool::from(extension.transferring),
+        TransferHookError::NotTransferring
+    );
+    Ok(())
+}
+
+/// The extra accounts of a transfer: the counter PDA of `b"counter"` and the
+/// mint, the mint being the second account of the execute instruction.
+fn extra_account_metas() -> Result<Vec<ExtraAccountMeta>> {
+    Ok(vec![ExtraAccountMeta::new_with_seeds(
+        &[
+            Seed::Literal {
+                bytes: b"counter".to_vec(),
+            },
+            Seed::AccountKey { index: 1 },
+        ],
+        false,
+        true,
+    )?])
+}
+
+/// Transfers of a mint, at the PDA of `b"counter"` and the mint.
+#[account]
+#[derive(Default)]
+pub struct Counter {
+    pub transfers: u64,
+    pub amount: u64,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct InitializeExtraAccountMetaList<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    ///CHECK: Initialized as the extra account metas of the mint.
+    #[account(
+        init,
+        payer = payer,
+        space = ExtraAccountMetaList::size_of(extra_account_metas()?.len())?,
+        seeds = [
+            b"extra-account-metas".as_ref(),
+            mint.key().as_ref()
+        ],
+        bump,
+    )]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        space = size_of::<Counter>() + 8,
+        seeds = [
+            b"counter".as_ref(),
+            mint.key().as_ref()
+        ],
+        bump,
+    )]
+    pub counter: Account<'info, Counter>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts of the execute instruction, in the order of the transfer hook
+/// interface followed by the extra accounts.
+#[derive(Accounts)]
+pub struct TransferHook<'info> {
+    #[account(token::mint = mint)]
+    pub source_token: InterfaceAccount<'info, TokenAccount>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(token::mint = mint)]
+    pub destination_token: InterfaceAccount<'info, TokenAccount>,
+    ///CHECK: Owner or delegate of the source token account, e.g. an HTLC PDA.
+    pub owner: UncheckedAccount<'info>,
+    ///CHECK: The extra account metas of the mint.
+    #[account(
+        seeds = [
+            b"extra-account-metas".as_ref(),
+            mint.key().as_ref()
+        ],
+        bump,
+    )]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"counter".as_ref(),
+            mint.key().as_ref()
+        ],
+        bump = counter.bump,
+    )]
+    pub counter: Account<'info, Counter>,
+}
+
+#[error_code]
+pub enum TransferHookError {
+    #[msg("Not called by the token program during a transfer.")]
+    NotTransferring,
+    #[msg("Counter overflow.")]
+    Overflow,
+}
@@ -4,8 +4,17 @@ import { Program } from "@coral-xyz/anchor";
 import { Keypair, PublicKey } from "@solana/web3.js";
 import { randomBytes, createHash } from "crypto";
 import * as spl from '@solana/spl-token';
+import { secp256k1 } from "@noble/curves/secp256k1";
+import { keccak_256 } from "@noble/hashes/sha3";
+import {
+  ConcurrentMerkleTreeAccount,
+  getConcurrentMerkleTreeAccountSize,
+  SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+  SPL_NOOP_PROGRAM_ID,
+} from "@solana/spl-account-compression";
 //import { TOKEN_2022_PROGRAM_ID, getAssociatedTokenAddressSync } from "@solana/spl-token";
 import { AnchorHtlc } from '../target/types/anchor_htlc';
+import { TransferHook } from '../target/types/transfer_hook';
 
 interface PDAParameters {
   htlcTokenAccount: anchor.web3.PublicKey;
@@ -17,6 +26,7 @@ describe("HTLC", () => {
   const provider = anchor.AnchorProvider.env();
   anchor.setProvider(provider);
   const program = anchor.workspace.AnchorHtlc as anchor.Program<AnchorHtlc>;
+  const hookProgram = anchor.workspace.TransferHook as anchor.Program<TransferHook>;
   const wallet = provider.wallet as anchor.Wallet;
 
   // const ID = new anchor.BN(24);
@@ -33,10 +43,10 @@ describe("HTLC", () => {
   const HASHLOCKArray: number[] = Array.from(HASHLOCK);
   //const TIMELOCK = new anchor.BN(Date.now() - 3);
   const AMOUNT = 1 * anchor.web3.LAMPORTS_PER_SOL;
-  const DSTCHAIN = "STARKNET_SEPOLIA";
+  const DSTCHAIN = "starknet:SN_SEPOLIA";
   const DSTADDRESS = "0x021b6a2ff227f1c71cc6536e7b9e8ecd0d5599b3a934279011e2f2b923d3a782";
-  const SRCASSET = "ETH";
-  const DSTASSET = "ETH";
+  const DSTASSET = "starknet:SN_SEPOLIA/slip44:9004";
+  const SRCCHAIN = "solana:EtWTRABZaYq6iMfeYKouRu166VU2xqa1";
   const HOPCHAINS = [DSTCHAIN];
   const HOPASSETS = [DSTASSET];
   const HOPADDRESSES = [DSTADDRESS];
@@ -45,6 +55,12 @@ describe("HTLC", () => {
   let walletTokenAccount: anchor.web3.PublicKey;
   let bob: anchor.web3.Keypair;
   let pda: PDAParameters;
+  // The CAIP-19 id of tokenMint, known once it is created.
+  let SRCASSET: string;
+  let ROUTE: { dstChainEntry: PublicKey, dstAssetEntry: PublicKey, srcAssetEntry: PublicKey };
+
+  const registryEntry = (kind: string, id: string): PublicKey =>
+    PublicKey.findProgramAddressSync([Buffer.from(kind), createHash("sha256").update(id).digest()], program.programId)[0];
 
   const getPdaParams = async (
     user: PublicKey,
@@ -93,6 +109,37 @@ describe("HTLC", () => {
     return tokenMint.publicKey;
   };
 
+  // A Token-2022 mint whose transfers call the example transfer hook, with
+  // `amount` minted to the wallet. Transfer hooks are rejected unless the admin
+  // accepts them, so its policy allows unsafe extensions.
+  const createHookMint = async (amount: number) => {
+    const mint = new Keypair();
+    const mintLen = spl.getMintLen([spl.ExtensionType.TransferHook]);
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.createAccount({
+          fromPubkey: wallet.publicKey,
+          newAccountPubkey: mint.publicKey,
+          space: mintLen,
+          lamports: await provider.connection.getMinimumBalanceForRentExemption(mintLen),
+          programId: spl.TOKEN_2022_PROGRAM_ID,
+        }),
+        spl.createInitializeTransferHookInstruction(mint.publicKey, wallet.publicKey, hookProgram.programId, spl.TOKEN_2022_PROGRAM_ID),
+        spl.createInitializeMintInstruction(mint.publicKey, 6, wallet.publicKey, null, spl.TOKEN_2022_PROGRAM_ID)
+      ),
+      [mint]
+    );
+    await hookProgram.methods.initializeExtraAccountMetaList()
+      .accountsPartial({ payer: wallet.publicKey, mint: mint.publicKey })
+      .rpc();
+    const senderTokenAccount = await spl.createAssociatedTokenAccount(provider.connection, wallet.payer, mint.publicKey, wallet.publicKey, undefined, spl.TOKEN_2022_PROGRAM_ID);
+    await spl.mintTo(provider.connection, wallet.payer, mint.publicKey, senderTokenAccount, wallet.payer, amount, [], undefined, spl.TOKEN_2022_PROGRAM_ID);
+    await program.methods.setMintPolicy(true, new anchor.BN(1000), new anchor.BN(AMOUNT), new anchor.BN(0), false, true)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, tokenContract: mint.publicKey })
+      .rpc();
+    return { mint, senderTokenAccount };
+  };
+
   const createUserAndAssociatedWallet = async (
     mint?: anchor.web3.PublicKey
   ): Promise<[anchor.web3.Keypair, anchor.web3.PublicKey | undefined]> => {
@@ -176,6 +223,40 @@ describe("HTLC", () => {
     walletTokenAccount = await mintTokensForUser(wallet.publicKey, tokenMint);
     [bob, ..._rest] = await createUserAndAssociatedWallet();
     pda = await getPdaParams(wallet.publicKey, ID);
+
+    // The wallet deployed the program, so it is the upgrade authority that sets the admin.
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    await program.methods.initializeConfig(wallet.publicKey)
+      .accountsPartial({ authority: wallet.publicKey, programData })
+      .rpc();
+    await program.methods.setMintPolicy(true, new anchor.BN(1000), new anchor.BN(AMOUNT), new anchor.BN(0), false, false)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, tokenContract: tokenMint })
+      .rpc();
+
+    SRCASSET = `${SRCCHAIN}/token:${tokenMint.toBase58()}`;
+    ROUTE = {
+      dstChainEntry: registryEntry("chain", DSTCHAIN),
+      dstAssetEntry: registryEntry("asset", DSTASSET),
+      srcAssetEntry: registryEntry("asset", SRCASSET),
+    };
+    for (const [chain, family] of [[DSTCHAIN, { starknet: {} }], [SRCCHAIN, { solana: {} }]] as [string, any][]) {
+      await program.methods.setChain(chain, family, true)
+        .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, chainEntry: registryEntry("chain", chain) })
+        .rpc();
+    }
+    for (const [chain, asset] of [[DSTCHAIN, DSTASSET], [SRCCHAIN, SRCASSET]]) {
+      await program.methods.setAsset(asset, true)
+        .accountsPartial({
+          admin: wallet.publicKey,
+          payer: wallet.publicKey,
+          chainEntry: registryEntry("chain", chain),
+          assetEntry: registryEntry("asset", asset),
+        })
+        .rpc();
+    }
   });
   // it("Create Prehtlc", async () => {
 
@@ -195,7 +276,7 @@ describe("HTLC", () => {
   //   console.log(`[${TIMELOCK * 1000}] the Timelock`);
 
   //   const commitTx = await program.methods
-  //     .commit(IDArray, HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, TIMELOCK, new anchor.BN(AMOUNT), pda.htlcBump)
+  //     .commit(IDArray, HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, TIMELOCK, { timestamp: {} }, new anchor.BN(AMOUNT), null, null, null, Buffer.alloc(0), pda.htlcBump)
   //     .accountsPartial({
   //       sender: wallet.publicKey,
   //       htlc: pda.htlc,
@@ -305,7 +386,7 @@ describe("HTLC", () => {
 
 
   //   const lockTx = await program.methods
-  //     .lock(IDArray, HASHLOCKArray, TIMELOCK, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(AMOUNT), pda.htlcBump)
+  //     .lock(IDArray, HASHLOCKArray, TIMELOCK, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(AMOUNT), null, [], Buffer.alloc(0), pda.htlcBump)
   //     .accountsPartial({
   //       sender: wallet.publicKey,
   //       htlc: pda.htlc,
@@ -368,12 +449,15 @@ describe("HTLC", () => {
     console.log(`[${TIME * 1000}] the Timelock`);
 
     const lockTx = await program.methods
-      .lock(IDArray, HASHLOCKArray, TIMELOCK, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(AMOUNT), pda.htlcBump)
+      .lock(IDArray, HASHLOCKArray, TIMELOCK, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(AMOUNT), null, [], Buffer.alloc(0), pda.htlcBump)
       .accountsPartial({
+        ...ROUTE,
         sender: wallet.publicKey,
+        payer: wallet.publicKey,
         htlc: pda.htlc,
         htlcTokenAccount: pda.htlcTokenAccount,
         tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
         senderTokenAccount: walletTokenAccount
       })
       .signers([wallet.payer])
@@ -395,7 +479,12 @@ describe("HTLC", () => {
         htlcTokenAccount: pda.htlcTokenAccount,
         sender: wallet.publicKey,
         tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
         senderTokenAccount: walletTokenAccount,
+        senderStats: PublicKey.findProgramAddressSync(
+          [Buffer.from("sender_stats"), wallet.publicKey.toBuffer(), tokenMint.toBuffer()],
+          program.programId
+        )[0],
       })
       .signers([wallet.payer])
       .rpc();
@@ -414,4 +503,1050 @@ describe("HTLC", () => {
 
   });
 
+  it("migrates an HTLC stored in the pre-versioning layout", async () => {
+    // Loaded into the validator from tests/fixtures/htlc_v0.json (see Anchor.toml).
+    const V0_ID = Buffer.alloc(32, 0x11);
+    const v0 = await getPdaParams(wallet.publicKey, V0_ID);
+
+    // An HTLC in an older layout is read without migrating it.
+    const stored = await program.methods.getDetails(Array.from(V0_ID)).accountsPartial({ htlc: v0.htlc }).view();
+    assert.equal(stored.version, 0);
+    assert.equal(stored.dstChain, "STARKNET_SEPOLIA");
+
+    // It can't be changed until it is migrated.
+    await assert.rejects(
+      program.methods.addLock(Array.from(V0_ID), Array.from(randomBytes(32)), new anchor.BN((new Date().getTime() + 60000) / 1000), [])
+        .accountsPartial({
+          sender: wallet.publicKey,
+          htlc: v0.htlc,
+        })
+        .rpc(),
+      /UnknownLayout/
+    );
+
+    await program.methods.migrate(Array.from(V0_ID))
+      .accountsPartial({
+        payer: wallet.publicKey,
+        htlc: v0.htlc,
+      })
+      .rpc();
+
+    const info = await provider.connection.getAccountInfo(v0.htlc);
+    assert.equal(info.data.length, 920);
+    const details = await program.methods.getDetails(Array.from(V0_ID)).accountsPartial({ htlc: v0.htlc }).view();
+    assert.equal(details.version, 1);
+    assert.equal(details.dstChain, "STARKNET_SEPOLIA");
+    assert.equal(details.amount.toString(), "1000000000");
+    assert.equal(details.tokenContract.toBase58(), new PublicKey(Buffer.alloc(32, 0x55)).toBase58());
+    assert.equal(details.tokenWallet.toBase58(), v0.htlcTokenAccount.toBase58());
+    assert.deepEqual(details.timelockKind, { timestamp: {} });
+
+    await assert.rejects(
+      program.methods.migrate(Array.from(V0_ID))
+        .accountsPartial({
+          payer: wallet.publicKey,
+          htlc: v0.htlc,
+        })
+        .rpc(),
+      /AlreadyMigrated/
+    );
+  });
+
+  it("reports the compute units used by lock and redeem", async () => {
+    const id = randomBytes(32);
+    const secret = randomBytes(32);
+    const hashlock = createHash("sha256").update(secret).digest();
+    const receiver = new Keypair();
+    const { htlc, htlcBump, htlcTokenAccount } = await getPdaParams(wallet.publicKey, id);
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    const unitsConsumed = async (signature: string) => {
+      const tx = await provider.connection.getTransaction(signature, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
+      return tx.meta.computeUnitsConsumed;
+    };
+
+    const lockTx = await program.methods
+      .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, receiver.publicKey, new anchor.BN(1000), null, [], Buffer.alloc(0), htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        senderTokenAccount: walletTokenAccount,
+      })
+      .rpc({ commitment: "confirmed" });
+    const redeemTx = await program.methods.redeem(Array.from(id), Array.from(secret), htlcBump)
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        sender: wallet.publicKey,
+        srcReceiver: receiver.publicKey,
+        tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        srcReceiverTokenAccount: spl.getAssociatedTokenAddressSync(tokenMint, receiver.publicKey),
+        senderStats: PublicKey.findProgramAddressSync(
+          [Buffer.from("sender_stats"), wallet.publicKey.toBuffer(), tokenMint.toBuffer()],
+          program.programId
+        )[0],
+        payoutTokenAccount: null,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("compute units: lock", await unitsConsumed(lockTx), "redeem", await unitsConsumed(redeemTx));
+  });
+
+  it("adds the lock with a signature of the sender's EVM key", async () => {
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    const evmKey = secp256k1.utils.randomPrivateKey();
+    const evmPublicKey = secp256k1.getPublicKey(evmKey, false).slice(1);
+    const commit = async () => {
+      const id = randomBytes(32);
+      const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+      const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+      const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+      await program.methods
+        .commit(Array.from(id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, { timestamp: {} }, new anchor.BN(1000), { publicKey: [Array.from(evmPublicKey)] }, null, null, Buffer.alloc(0), htlcBump)
+        .accountsPartial({
+          ...ROUTE,
+          sender: wallet.publicKey,
+          payer: wallet.publicKey,
+          htlc,
+          htlcTokenAccount,
+          tokenContract: tokenMint,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          senderTokenAccount: walletTokenAccount,
+        })
+        .rpc();
+      return { id, hashlock, htlc };
+    };
+    // EIP-712 digest of addLockMsg(bytes32 Id,bytes32 hashlock,uint256 timelock), see add_lock_digest.
+    const addLockDigest = ({ id, hashlock }: { id: Buffer, hashlock: Buffer }, lockTimelock: anchor.BN) => {
+      const domainSeparator = keccak_256(Buffer.concat([
+        keccak_256("EIP712Domain(string name,string version,bytes32 salt)"),
+        keccak_256("LayerswapV8"),
+        keccak_256("1"),
+        program.programId.toBuffer(),
+      ]));
+      const structHash = keccak_256(Buffer.concat([
+        keccak_256("addLockMsg(bytes32 Id,bytes32 hashlock,uint256 timelock)"),
+        id,
+        hashlock,
+        lockTimelock.toArrayLike(Buffer, "be", 32),
+      ]));
+      return keccak_256(Buffer.concat([Buffer.from([0x19, 0x01]), domainSeparator, structHash]));
+    };
+    // The EIP-191 personal message of the EIP-712 digest, as personal_sign signs it.
+    const personalMessage = (digest: Uint8Array) =>
+      keccak_256(Buffer.concat([Buffer.from("\x19Ethereum Signed Message:\n32"), digest]));
+    const addLockSig = (
+      { id, hashlock, htlc }: { id: Buffer, hashlock: Buffer, htlc: PublicKey },
+      messageKind: { typedData: {} } | { personalMessage: {} },
+      digest: Uint8Array,
+      key: Uint8Array,
+    ) => {
+      const signature = secp256k1.sign(digest, key);
+      return program.methods
+        .addLockSig(Array.from(id), Array.from(hashlock), timelock, messageKind, Array.from(signature.toCompactRawBytes()), signature.recovery)
+        .accountsPartial({
+          payer: wallet.publicKey,
+          htlc,
+          hashlockIndex: PublicKey.findProgramAddressSync([Buffer.from("hashlock_index"), wallet.publicKey.toBuffer(), hashlock], program.programId)[0],
+        })
+        .rpc();
+    };
+
+    const typed = await commit();
+    await assert.rejects(addLockSig(typed, { typedData: {} }, addLockDigest(typed, timelock), secp256k1.utils.randomPrivateKey()), /InvalidSignature/);
+    await assert.rejects(addLockSig(typed, { typedData: {} }, addLockDigest(typed, timelock.addn(1)), evmKey), /InvalidSignature/);
+    await addLockSig(typed, { typedData: {} }, addLockDigest(typed, timelock), evmKey);
+    const details = await program.methods.getDetails(Array.from(typed.id)).accountsPartial({ htlc: typed.htlc }).view();
+    assert.deepEqual(details.hashlock, Array.from(typed.hashlock));
+
+    const personal = await commit();
+    // A typed data signature doesn't pass as a personal message.
+    await assert.rejects(addLockSig(personal, { personalMessage: {} }, addLockDigest(personal, timelock), evmKey), /InvalidSignature/);
+    await assert.rejects(
+      addLockSig(personal, { personalMessage: {} }, personalMessage(addLockDigest(personal, timelock)), secp256k1.utils.randomPrivateKey()),
+      /InvalidSignature/
+    );
+    await addLockSig(personal, { personalMessage: {} }, personalMessage(addLockDigest(personal, timelock)), evmKey);
+    const personalDetails = await program.methods.getDetails(Array.from(personal.id)).accountsPartial({ htlc: personal.htlc }).view();
+    assert.deepEqual(personalDetails.hashlock, Array.from(personal.hashlock));
+  });
+
+  it("reports many HTLCs with one getDetailsBatch call", async () => {
+    const v0 = await getPdaParams(wallet.publicKey, Buffer.alloc(32, 0x11));
+    const missing = await getPdaParams(wallet.publicKey, randomBytes(32));
+    const summaries = await program.methods.getDetailsBatch()
+      .remainingAccounts([v0.htlc, missing.htlc].map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })))
+      .view();
+    assert.deepEqual(summaries.map((summary) => summary.status), [{ locked: {} }, { missing: {} }]);
+    assert.equal(summaries[0].amount.toString(), "1000000000");
+  });
+
+  it("rejects HTLCs outside of the mint policy", async () => {
+    const lockWithAmount = (amount: anchor.BN) => {
+      const id = randomBytes(32);
+      const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+      const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+      const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+      const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+      return program.methods
+        .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, amount, null, [], Buffer.alloc(0), htlcBump)
+        .accountsPartial({
+          ...ROUTE,
+          sender: wallet.publicKey,
+          payer: wallet.publicKey,
+          htlc,
+          htlcTokenAccount,
+          tokenContract: tokenMint,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          senderTokenAccount: walletTokenAccount,
+        })
+        .rpc();
+    };
+
+    await assert.rejects(lockWithAmount(new anchor.BN(999)), /AmountTooSmall/);
+    await assert.rejects(lockWithAmount(new anchor.BN(AMOUNT + 1)), /AmountTooLarge/);
+
+    await program.methods.setMintPolicy(false, new anchor.BN(1000), new anchor.BN(AMOUNT), new anchor.BN(0), false, false)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, tokenContract: tokenMint })
+      .rpc();
+    await assert.rejects(lockWithAmount(new anchor.BN(1000)), /MintNotAllowed/);
+
+    // At most 1500 locked per sender, so only one HTLC of 1000 fits.
+    await program.methods.setMintPolicy(true, new anchor.BN(1000), new anchor.BN(AMOUNT), new anchor.BN(1500), false, false)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, tokenContract: tokenMint })
+      .rpc();
+    await lockWithAmount(new anchor.BN(1000));
+    await assert.rejects(lockWithAmount(new anchor.BN(1000)), /LockedLimitExceeded/);
+    await program.methods.setMintPolicy(true, new anchor.BN(1000), new anchor.BN(AMOUNT), new anchor.BN(0), false, false)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, tokenContract: tokenMint })
+      .rpc();
+  });
+
+  it("locks an NFT and rejects fungible mints in NFT mode", async () => {
+    const nftMint = await spl.createMint(provider.connection, wallet.payer, wallet.publicKey, null, 0);
+    const walletNftAccount = await spl.createAssociatedTokenAccount(provider.connection, wallet.payer, nftMint, wallet.publicKey);
+    await spl.mintTo(provider.connection, wallet.payer, nftMint, walletNftAccount, wallet.payer, 1);
+    await program.methods.setMintPolicy(true, new anchor.BN(1), new anchor.BN(1), new anchor.BN(0), false, false)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, tokenContract: nftMint })
+      .rpc();
+
+    const lockNft = (mint: PublicKey, senderTokenAccount: PublicKey) => {
+      const id = randomBytes(32);
+      const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+      const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+      const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+      const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+      return {
+        id,
+        htlc,
+        tx: program.methods
+          .lockNft(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, null, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, null, Buffer.alloc(0), htlcBump)
+          .accountsPartial({
+            ...ROUTE,
+            sender: wallet.publicKey,
+            payer: wallet.publicKey,
+            htlc,
+            htlcTokenAccount,
+            tokenContract: mint,
+            tokenProgram: spl.TOKEN_PROGRAM_ID,
+            metadata: null,
+            senderTokenAccount,
+          })
+          .rpc(),
+      };
+    };
+
+    await assert.rejects(lockNft(tokenMint, walletTokenAccount).tx, /NotNft/);
+
+    const locked = lockNft(nftMint, walletNftAccount);
+    await locked.tx;
+    const details = await program.methods.getDetails(Array.from(locked.id)).accountsPartial({ htlc: locked.htlc }).view();
+    assert.equal(details.nft, true);
+    assert.equal(details.amount.toString(), "1");
+    assert.equal(details.collection.toBase58(), PublicKey.default.toBase58());
+  });
+
+  it("redirects the payout of a frozen token account and rejects mints with a freeze authority by policy", async () => {
+    const id = randomBytes(32);
+    const secret = randomBytes(32);
+    const hashlock = createHash("sha256").update(secret).digest();
+    const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+    const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    const receiver = new Keypair();
+    const lock = (lockId: Buffer, lockHashlock: Buffer, lockHtlc: PublicKey, lockHtlcTokenAccount: PublicKey, bump: number) => program.methods
+      .lock(Array.from(lockId), Array.from(lockHashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, receiver.publicKey, new anchor.BN(1000), null, [], Buffer.alloc(0), bump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc: lockHtlc,
+        htlcTokenAccount: lockHtlcTokenAccount,
+        tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        senderTokenAccount: walletTokenAccount,
+      })
+      .rpc();
+    await lock(id, hashlock, htlc, htlcTokenAccount, htlcBump);
+
+    // The wallet is the freeze authority of the mint.
+    const receiverTokenAccount = await spl.createAssociatedTokenAccount(provider.connection, wallet.payer, tokenMint, receiver.publicKey);
+    await spl.freezeAccount(provider.connection, wallet.payer, receiverTokenAccount, tokenMint, wallet.payer);
+    const payoutTokenAccount = await spl.createAccount(provider.connection, wallet.payer, tokenMint, receiver.publicKey, new Keypair());
+    const redeem = (payout: PublicKey | null) => program.methods.redeem(Array.from(id), Array.from(secret), htlcBump)
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        sender: wallet.publicKey,
+        srcReceiver: receiver.publicKey,
+        tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        srcReceiverTokenAccount: receiverTokenAccount,
+        senderStats: PublicKey.findProgramAddressSync(
+          [Buffer.from("sender_stats"), wallet.publicKey.toBuffer(), tokenMint.toBuffer()],
+          program.programId
+        )[0],
+        payoutTokenAccount: payout,
+      });
+    await assert.rejects(redeem(null).rpc(), /TokenAccountFrozen/);
+    await redeem(payoutTokenAccount).signers([receiver]).rpc();
+    const [, payoutBalance] = await readAccount(payoutTokenAccount, provider);
+    assert.equal(payoutBalance, "1000");
+
+    await program.methods.setMintPolicy(true, new anchor.BN(1000), new anchor.BN(AMOUNT), new anchor.BN(0), true, false)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, tokenContract: tokenMint })
+      .rpc();
+    const otherId = randomBytes(32);
+    const [otherHtlc, otherBump] = PublicKey.findProgramAddressSync([otherId], program.programId);
+    const [otherHtlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), otherId], program.programId);
+    await assert.rejects(lock(otherId, createHash("sha256").update(randomBytes(32)).digest(), otherHtlc, otherHtlcTokenAccount, otherBump), /MintHasFreezeAuthority/);
+    await program.methods.setMintPolicy(true, new anchor.BN(1000), new anchor.BN(AMOUNT), new anchor.BN(0), false, false)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, tokenContract: tokenMint })
+      .rpc();
+  });
+
+  it("rejects Token-2022 mints with unsafe extensions unless the admin allows them", async () => {
+    const mint = new Keypair();
+    const mintLen = spl.getMintLen([spl.ExtensionType.PermanentDelegate]);
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.createAccount({
+          fromPubkey: wallet.publicKey,
+          newAccountPubkey: mint.publicKey,
+          space: mintLen,
+          lamports: await provider.connection.getMinimumBalanceForRentExemption(mintLen),
+          programId: spl.TOKEN_2022_PROGRAM_ID,
+        }),
+        spl.createInitializePermanentDelegateInstruction(mint.publicKey, wallet.publicKey, spl.TOKEN_2022_PROGRAM_ID),
+        spl.createInitializeMintInstruction(mint.publicKey, 6, wallet.publicKey, null, spl.TOKEN_2022_PROGRAM_ID)
+      ),
+      [mint]
+    );
+    const senderTokenAccount = await spl.createAssociatedTokenAccount(provider.connection, wallet.payer, mint.publicKey, wallet.publicKey, undefined, spl.TOKEN_2022_PROGRAM_ID);
+    await spl.mintTo(provider.connection, wallet.payer, mint.publicKey, senderTokenAccount, wallet.payer, 1000, [], undefined, spl.TOKEN_2022_PROGRAM_ID);
+
+    const setMintPolicy = (allowUnsafeExtensions: boolean) => program.methods
+      .setMintPolicy(true, new anchor.BN(1000), new anchor.BN(AMOUNT), new anchor.BN(0), false, allowUnsafeExtensions)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, tokenContract: mint.publicKey })
+      .rpc();
+    const lock = () => {
+      const id = randomBytes(32);
+      const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+      const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+      const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+      const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+      return program.methods
+        .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(1000), null, [], Buffer.alloc(0), htlcBump)
+        .accountsPartial({
+          ...ROUTE,
+          sender: wallet.publicKey,
+          payer: wallet.publicKey,
+          htlc,
+          htlcTokenAccount,
+          tokenContract: mint.publicKey,
+          tokenProgram: spl.TOKEN_2022_PROGRAM_ID,
+          senderTokenAccount,
+        })
+        .rpc();
+    };
+
+    await setMintPolicy(false);
+    await assert.rejects(lock(), /MintHasPermanentDelegate/);
+    await setMintPolicy(true);
+    await lock();
+  });
+
+  it("forwards the accounts of a transfer hook in lock and redeem", async () => {
+    const { mint, senderTokenAccount } = await createHookMint(1000);
+
+    const id = randomBytes(32);
+    const secret = randomBytes(32);
+    const hashlock = createHash("sha256").update(secret).digest();
+    const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+    const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+    const bobTokenAccount = spl.getAssociatedTokenAddressSync(mint.publicKey, bob.publicKey, false, spl.TOKEN_2022_PROGRAM_ID);
+    // Resolves the extra accounts of a transfer from the extra account metas of the hook.
+    const hookAccounts = async (source: PublicKey, destination: PublicKey, owner: PublicKey) => {
+      const instruction = new anchor.web3.TransactionInstruction({ keys: [], programId: spl.TOKEN_2022_PROGRAM_ID });
+      await spl.addExtraAccountMetasForExecute(provider.connection, instruction, hookProgram.programId, source, mint.publicKey, destination, owner, 1000);
+      return instruction.keys;
+    };
+
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    await program.methods
+      .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(1000), null, [], Buffer.alloc(0), htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        tokenContract: mint.publicKey,
+        tokenProgram: spl.TOKEN_2022_PROGRAM_ID,
+        senderTokenAccount,
+      })
+      .remainingAccounts(await hookAccounts(senderTokenAccount, htlcTokenAccount, wallet.publicKey))
+      .rpc();
+    await program.methods.redeem(Array.from(id), Array.from(secret), htlcBump)
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        sender: wallet.publicKey,
+        srcReceiver: bob.publicKey,
+        tokenContract: mint.publicKey,
+        tokenProgram: spl.TOKEN_2022_PROGRAM_ID,
+        srcReceiverTokenAccount: bobTokenAccount,
+        senderStats: PublicKey.findProgramAddressSync(
+          [Buffer.from("sender_stats"), wallet.publicKey.toBuffer(), mint.publicKey.toBuffer()],
+          program.programId
+        )[0],
+      })
+      .remainingAccounts(await hookAccounts(htlcTokenAccount, bobTokenAccount, htlc))
+      .rpc();
+
+    const bobAccount = await spl.getAccount(provider.connection, bobTokenAccount, undefined, spl.TOKEN_2022_PROGRAM_ID);
+    assert.equal(bobAccount.amount.toString(), "1000");
+    const [counter] = PublicKey.findProgramAddressSync([Buffer.from("counter"), mint.publicKey.toBuffer()], hookProgram.programId);
+    assert.equal((await hookProgram.account.counter.fetch(counter)).transfers.toString(), "2");
+
+    // Outside of a transfer the source account isn't transferring, so the
+    // hook can't be called directly to bump the counter.
+    await assert.rejects(
+      hookProgram.methods.transferHook(new anchor.BN(1000))
+        .accountsPartial({ sourceToken: senderTokenAccount, mint: mint.publicKey, destinationToken: bobTokenAccount, owner: wallet.publicKey })
+        .rpc(),
+      /NotTransferring/
+    );
+    assert.equal((await hookProgram.account.counter.fetch(counter)).transfers.toString(), "2");
+  });
+
+  it("splits the redeemed tokens between the receivers ahead of the hook accounts", async () => {
+    const { mint, senderTokenAccount } = await createHookMint(10003);
+
+    const id = randomBytes(32);
+    const secret = randomBytes(32);
+    const hashlock = createHash("sha256").update(secret).digest();
+    const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+    const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+    const lp = new Keypair();
+    const partner = new Keypair();
+    const primary = new Keypair();
+    const tokenAccountOf = (owner: PublicKey) =>
+      spl.createAssociatedTokenAccount(provider.connection, wallet.payer, mint.publicKey, owner, undefined, spl.TOKEN_2022_PROGRAM_ID);
+    const lpTokenAccount = await tokenAccountOf(lp.publicKey);
+    const partnerTokenAccount = await tokenAccountOf(partner.publicKey);
+    const primaryTokenAccount = spl.getAssociatedTokenAddressSync(mint.publicKey, primary.publicKey, false, spl.TOKEN_2022_PROGRAM_ID);
+    const splits = [
+      { receiver: lp.publicKey, share: 2500 },
+      { receiver: partner.publicKey, share: 1000 },
+    ];
+    // Resolves the extra accounts of a transfer from the extra account metas of the hook.
+    const hookAccounts = async (source: PublicKey, destination: PublicKey, owner: PublicKey) => {
+      const instruction = new anchor.web3.TransactionInstruction({ keys: [], programId: spl.TOKEN_2022_PROGRAM_ID });
+      await spl.addExtraAccountMetasForExecute(provider.connection, instruction, hookProgram.programId, source, mint.publicKey, destination, owner, 10003);
+      return instruction.keys;
+    };
+
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    await program.methods
+      .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, primary.publicKey, new anchor.BN(10003), null, splits, Buffer.alloc(0), htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        tokenContract: mint.publicKey,
+        tokenProgram: spl.TOKEN_2022_PROGRAM_ID,
+        senderTokenAccount,
+      })
+      .remainingAccounts(await hookAccounts(senderTokenAccount, htlcTokenAccount, wallet.publicKey))
+      .rpc();
+
+    const hook = await hookAccounts(htlcTokenAccount, primaryTokenAccount, htlc);
+    const splitAccounts = (tokenAccounts: PublicKey[]) =>
+      tokenAccounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }));
+    const redeem = (remainingAccounts: anchor.web3.AccountMeta[]) => program.methods.redeem(Array.from(id), Array.from(secret), htlcBump)
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        sender: wallet.publicKey,
+        srcReceiver: primary.publicKey,
+        tokenContract: mint.publicKey,
+        tokenProgram: spl.TOKEN_2022_PROGRAM_ID,
+        srcReceiverTokenAccount: primaryTokenAccount,
+        senderStats: PublicKey.findProgramAddressSync(
+          [Buffer.from("sender_stats"), wallet.publicKey.toBuffer(), mint.publicKey.toBuffer()],
+          program.programId
+        )[0],
+        payoutTokenAccount: null,
+      })
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+
+    // The token accounts of the split receivers come first, in the order of the splits.
+    await assert.rejects(redeem([]), /NotSplitReceiver/);
+    await assert.rejects(redeem([...splitAccounts([partnerTokenAccount, lpTokenAccount]), ...hook]), /NotSplitReceiver/);
+    // A token account of the mint owned by another wallet than the split receiver.
+    const otherTokenAccount = await spl.createAccount(provider.connection, wallet.payer, mint.publicKey, new Keypair().publicKey, new Keypair(), undefined, spl.TOKEN_2022_PROGRAM_ID);
+    await assert.rejects(redeem([...splitAccounts([lpTokenAccount, otherTokenAccount]), ...hook]), /NotSplitReceiver/);
+
+    await redeem([...splitAccounts([lpTokenAccount, partnerTokenAccount]), ...hook]);
+    const balanceOf = async (tokenAccount: PublicKey) =>
+      (await spl.getAccount(provider.connection, tokenAccount, undefined, spl.TOKEN_2022_PROGRAM_ID)).amount.toString();
+    assert.equal(await balanceOf(lpTokenAccount), "2500");
+    assert.equal(await balanceOf(partnerTokenAccount), "1000");
+    // The primary receiver gets the rest, including the rounding dust.
+    assert.equal(await balanceOf(primaryTokenAccount), "6503");
+  });
+
+  it("tops up a committed HTLC within the mint policy", async () => {
+    const id = randomBytes(32);
+    const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+    const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    await program.methods
+      .commit(Array.from(id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, { timestamp: {} }, new anchor.BN(1000), null, null, null, Buffer.alloc(0), htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        senderTokenAccount: walletTokenAccount,
+      })
+      .rpc();
+    const topUp = (sender: Keypair, amount: anchor.BN) => program.methods.topUp(Array.from(id), amount)
+      .accountsPartial({
+        sender: sender.publicKey,
+        htlc,
+        htlcTokenAccount,
+        tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        senderTokenAccount: walletTokenAccount,
+        senderStats: PublicKey.findProgramAddressSync(
+          [Buffer.from("sender_stats"), wallet.publicKey.toBuffer(), tokenMint.toBuffer()],
+          program.programId
+        )[0],
+      })
+      .signers([sender])
+      .rpc();
+
+    await topUp(wallet.payer, new anchor.BN(1000));
+    await assert.rejects(topUp(bob, new anchor.BN(1000)), /UnauthorizedAccess/);
+    // The total of 2000 plus AMOUNT is above the max_amount of the mint policy.
+    await assert.rejects(topUp(wallet.payer, new anchor.BN(AMOUNT)), /AmountTooLarge/);
+
+    const details = await program.methods.getDetails(Array.from(id)).accountsPartial({ htlc }).view();
+    assert.equal(details.amount.toString(), "2000");
+    const [, htlcBalance] = await readAccount(htlcTokenAccount, provider);
+    assert.equal(htlcBalance, "2000");
+  });
+
+  it("refunds an HTLC with a slot timelock only after the slot", async () => {
+    const id = randomBytes(32);
+    const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+    const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+    const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+    const timelock = (await provider.connection.getSlot()) + 10;
+    await program.methods
+      .lock(Array.from(id), Array.from(hashlock), new anchor.BN(timelock), { slot: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(1000), null, [], Buffer.alloc(0), htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        senderTokenAccount: walletTokenAccount,
+      })
+      .rpc();
+    const refund = () => program.methods.refund(Array.from(id), htlcBump)
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        sender: wallet.publicKey,
+        tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        senderTokenAccount: walletTokenAccount,
+        senderStats: PublicKey.findProgramAddressSync(
+          [Buffer.from("sender_stats"), wallet.publicKey.toBuffer(), tokenMint.toBuffer()],
+          program.programId
+        )[0],
+      })
+      .rpc();
+
+    await assert.rejects(refund(), /NotPastTimeLock/);
+    while ((await provider.connection.getSlot()) < timelock) {
+      await wait(400);
+    }
+    await refund();
+    const details = await program.methods.getDetails(Array.from(id)).accountsPartial({ htlc }).view();
+    assert.deepEqual(details.timelockKind, { slot: {} });
+    assert.equal(details.refunded, true);
+  });
+
+  it("redeems by hashlock through the hashlock index of the sender", async () => {
+    const secret = randomBytes(32);
+    const hashlock = createHash("sha256").update(secret).digest();
+    const receiver = new Keypair();
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    const lockAs = async (sender: Keypair, senderTokenAccount: PublicKey) => {
+      const id = randomBytes(32);
+      const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+      const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+      await program.methods
+        .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, receiver.publicKey, new anchor.BN(1000), null, [], Buffer.alloc(0), htlcBump)
+        .accountsPartial({
+          ...ROUTE,
+          sender: sender.publicKey,
+          payer: wallet.publicKey,
+          htlc,
+          htlcTokenAccount,
+          tokenContract: tokenMint,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          senderTokenAccount,
+        })
+        .signers([sender])
+        .rpc();
+      return { htlc, htlcBump, htlcTokenAccount };
+    };
+
+    // Someone else locking the hashlock first doesn't block the sender.
+    const [other, otherTokenAccount] = await createUserAndAssociatedWallet(tokenMint);
+    await lockAs(other, otherTokenAccount);
+    const { htlc, htlcBump, htlcTokenAccount } = await lockAs(wallet.payer, walletTokenAccount);
+    await assert.rejects(lockAs(wallet.payer, walletTokenAccount), /HashlockAlreadyUsed/);
+
+    const receiverTokenAccount = spl.getAssociatedTokenAddressSync(tokenMint, receiver.publicKey);
+    await program.methods.redeemByHashlock(Array.from(hashlock), Array.from(secret), htlcBump)
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        hashlockIndex: PublicKey.findProgramAddressSync([Buffer.from("hashlock_index"), wallet.publicKey.toBuffer(), hashlock], program.programId)[0],
+        htlc,
+        htlcTokenAccount,
+        sender: wallet.publicKey,
+        srcReceiver: receiver.publicKey,
+        tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        srcReceiverTokenAccount: receiverTokenAccount,
+        senderStats: PublicKey.findProgramAddressSync(
+          [Buffer.from("sender_stats"), wallet.publicKey.toBuffer(), tokenMint.toBuffer()],
+          program.programId
+        )[0],
+        payoutTokenAccount: null,
+      })
+      .rpc();
+    const [, receiverBalance] = await readAccount(receiverTokenAccount, provider);
+    assert.equal(receiverBalance, "1000");
+  });
+
+  const compressedTree = async (mint = tokenMint, tokenProgram = spl.TOKEN_PROGRAM_ID) => {
+    const MAX_DEPTH = 3;
+    const MAX_BUFFER_SIZE = 8;
+    const merkleTree = new Keypair();
+    const space = getConcurrentMerkleTreeAccountSize(MAX_DEPTH, MAX_BUFFER_SIZE);
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.createAccount({
+          fromPubkey: wallet.publicKey,
+          newAccountPubkey: merkleTree.publicKey,
+          lamports: await provider.connection.getMinimumBalanceForRentExemption(space),
+          space,
+          programId: SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+        })
+      ),
+      [merkleTree]
+    );
+    const treeAccounts = {
+      merkleTree: merkleTree.publicKey,
+      tokenContract: mint,
+      tokenProgram,
+      compressionProgram: SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+      noopProgram: SPL_NOOP_PROGRAM_ID,
+    };
+    await program.methods.initCompressedTree(MAX_DEPTH, MAX_BUFFER_SIZE)
+      .accountsPartial({ payer: wallet.publicKey, ...treeAccounts })
+      .rpc();
+    // The first leaf is at index 0, so its proof consists of the empty subtrees.
+    const proof: PublicKey[] = [];
+    let node = Buffer.alloc(32);
+    for (let level = 0; level < MAX_DEPTH; level++) {
+      proof.push(new PublicKey(node));
+      node = Buffer.from(keccak_256(Buffer.concat([node, node])));
+    }
+    const remainingAccounts = proof.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }));
+    const root = async () =>
+      Array.from((await ConcurrentMerkleTreeAccount.fromAccountAddress(provider.connection, merkleTree.publicKey)).getCurrentRoot());
+    return { treeAccounts, remainingAccounts, root };
+  };
+
+  const lockCompressed = async (
+    treeAccounts: { merkleTree: PublicKey },
+    receiver: PublicKey,
+    timelock: anchor.BN,
+    secret = randomBytes(32),
+  ) => {
+    const id = randomBytes(32);
+    const hashlock = createHash("sha256").update(secret).digest();
+    await program.methods
+      .lockCompressed(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, receiver, new anchor.BN(1000), null, Buffer.alloc(0))
+      .accountsPartial({
+        ...ROUTE,
+        ...treeAccounts,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        senderTokenAccount: walletTokenAccount,
+      })
+      .rpc();
+    const leaf = {
+      id: Array.from(id),
+      sender: wallet.publicKey,
+      srcReceiver: receiver,
+      refundTo: wallet.publicKey,
+      hashlock: Array.from(hashlock),
+      secret: Array.from(ZEROS),
+      amount: new anchor.BN(1000),
+      timelock,
+      timelockKind: { timestamp: {} },
+      redeemed: false,
+      refunded: false,
+    };
+    return { leaf, secret, hashlock };
+  };
+
+  const senderStatsOf = (sender: PublicKey) => PublicKey.findProgramAddressSync(
+    [Buffer.from("sender_stats"), sender.toBuffer(), tokenMint.toBuffer()],
+    program.programId
+  )[0];
+
+  it("locks and redeems a compressed HTLC and registers its hashlock", async () => {
+    const { treeAccounts, remainingAccounts, root } = await compressedTree();
+    const receiver = new Keypair();
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    const { leaf, secret, hashlock } = await lockCompressed(treeAccounts, receiver.publicKey, timelock);
+
+    const [hashlockIndex] = PublicKey.findProgramAddressSync([Buffer.from("hashlock_index"), wallet.publicKey.toBuffer(), hashlock], program.programId);
+    assert.deepEqual((await program.account.hashlockIndex.fetch(hashlockIndex)).id, leaf.id);
+    // The hashlock can't be reused by the same sender, compressed or not.
+    await assert.rejects(lockCompressed(treeAccounts, receiver.publicKey, timelock, secret), /HashlockAlreadyUsed/);
+
+    const receiverTokenAccount = spl.getAssociatedTokenAddressSync(tokenMint, receiver.publicKey);
+    const redeem = async (htlc: typeof leaf) => program.methods
+      .redeemCompressed(htlc, 0, await root(), Array.from(secret))
+      .accountsPartial({
+        ...treeAccounts,
+        userSigning: wallet.publicKey,
+        srcReceiver: receiver.publicKey,
+        srcReceiverTokenAccount: receiverTokenAccount,
+        senderStats: senderStatsOf(wallet.publicKey),
+      })
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+
+    // A leaf that is not in the tree fails the proof.
+    await assert.rejects(redeem({ ...leaf, amount: new anchor.BN(2000) }));
+    await redeem(leaf);
+    const [, receiverBalance] = await readAccount(receiverTokenAccount, provider);
+    assert.equal(receiverBalance, "1000");
+    // The leaf now records the redeem, so the same call fails the proof.
+    await assert.rejects(redeem(leaf));
+  });
+
+  it("refunds a compressed HTLC only after its timelock", async () => {
+    const { treeAccounts, remainingAccounts, root } = await compressedTree();
+    const timelock = new anchor.BN((new Date().getTime() + 15000) / 1000);
+    const { leaf } = await lockCompressed(treeAccounts, bob.publicKey, timelock);
+    const [, walletBalanceLocked] = await readAccount(walletTokenAccount, provider);
+
+    const refund = async () => program.methods
+      .refundCompressed(leaf, 0, await root())
+      .accountsPartial({
+        ...treeAccounts,
+        userSigning: wallet.publicKey,
+        refundTokenAccount: walletTokenAccount,
+        senderStats: senderStatsOf(wallet.publicKey),
+      })
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+
+    await assert.rejects(refund(), /NotPastTimeLock/);
+    await wait(20000);
+    await refund();
+    const [, walletBalanceRefunded] = await readAccount(walletTokenAccount, provider);
+    assert.equal(Number(walletBalanceRefunded) - Number(walletBalanceLocked), 1000);
+  });
+
+
+  it("rejects compressed HTLCs of mints with a transfer hook even if the policy accepts them", async () => {
+    const { mint, senderTokenAccount } = await createHookMint(1000);
+    const { treeAccounts } = await compressedTree(mint.publicKey, spl.TOKEN_2022_PROGRAM_ID);
+    const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    await assert.rejects(
+      program.methods
+        .lockCompressed(Array.from(randomBytes(32)), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(1000), null, Buffer.alloc(0))
+        .accountsPartial({
+          ...ROUTE,
+          ...treeAccounts,
+          sender: wallet.publicKey,
+          payer: wallet.publicKey,
+          senderTokenAccount,
+        })
+        .rpc(),
+      /MintHasTransferHook/
+    );
+  });
+
+  it("caps the open HTLCs of a sender", async () => {
+    const lockAs = (sender: Keypair, senderTokenAccount: PublicKey) => {
+      const id = randomBytes(32);
+      const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+      const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+      const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+      const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+      return program.methods
+        .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(1000), null, [], Buffer.alloc(0), htlcBump)
+        .accountsPartial({
+          ...ROUTE,
+          sender: sender.publicKey,
+          payer: wallet.publicKey,
+          htlc,
+          htlcTokenAccount,
+          tokenContract: tokenMint,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          senderTokenAccount,
+        })
+        .signers([sender])
+        .rpc();
+    };
+
+    const [sender, senderTokenAccount] = await createUserAndAssociatedWallet(tokenMint);
+    await program.methods.setLimits(1).accountsPartial({ admin: wallet.publicKey }).rpc();
+    await lockAs(sender, senderTokenAccount);
+    await assert.rejects(lockAs(sender, senderTokenAccount), /TooManyOpenHtlcs/);
+    await program.methods.setLimits(0).accountsPartial({ admin: wallet.publicKey }).rpc();
+
+    const [stats] = PublicKey.findProgramAddressSync(
+      [Buffer.from("sender_stats"), sender.publicKey.toBuffer(), tokenMint.toBuffer()],
+      program.programId
+    );
+    const { openHtlcs, locked } = await program.account.senderStats.fetch(stats);
+    assert.equal(openHtlcs, 1);
+    assert.equal(locked.toString(), "1000");
+  });
+
+  it("only locks routes of enabled chains and assets of the registry to valid addresses", async () => {
+    const lockWith = (dstAsset: string, route: typeof ROUTE, dstAddress = DSTADDRESS) => {
+      const id = randomBytes(32);
+      const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+      const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+      const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+      const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+      return program.methods
+        .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, dstAddress, dstAsset, SRCASSET, bob.publicKey, new anchor.BN(1000), null, [], Buffer.alloc(0), htlcBump)
+        .accountsPartial({
+          ...route,
+          sender: wallet.publicKey,
+          payer: wallet.publicKey,
+          htlc,
+          htlcTokenAccount,
+          tokenContract: tokenMint,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          senderTokenAccount: walletTokenAccount,
+        })
+        .rpc();
+    };
+
+    // The source asset is registered, but not on the destination chain.
+    await assert.rejects(lockWith(SRCASSET, { ...ROUTE, dstAssetEntry: ROUTE.srcAssetEntry }), /AssetNotRegistered/);
+    await assert.rejects(program.methods.setChain("STARKNET_SEPOLIA", { starknet: {} }, true)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, chainEntry: registryEntry("chain", "STARKNET_SEPOLIA") })
+      .rpc(), /InvalidCaipId/);
+    // Bitcoin chains have to be one of the known bip122 networks.
+    await assert.rejects(program.methods.setChain("bip122:00000000000000000000000000000000", { bitcoin: {} }, true)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, chainEntry: registryEntry("chain", "bip122:00000000000000000000000000000000") })
+      .rpc(), /InvalidCaipId/);
+
+    // A felt of 2^251 is past the Starknet address range.
+    await assert.rejects(lockWith(DSTASSET, ROUTE, "0x08" + "0".repeat(62)), /InvalidDestinationAddress/);
+
+    const setDstChain = (enabled: boolean) => program.methods.setChain(DSTCHAIN, { starknet: {} }, enabled)
+      .accountsPartial({ admin: wallet.publicKey, payer: wallet.publicKey, chainEntry: ROUTE.dstChainEntry })
+      .rpc();
+    await setDstChain(false);
+    await assert.rejects(lockWith(DSTASSET, ROUTE), /ChainNotRegistered/);
+    await setDstChain(true);
+  });
+
+  it("links an HTLC to its counterparty on the destination chain", async () => {
+    const id = randomBytes(32);
+    const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+    const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+    const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    await program.methods
+      .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(1000), null, [], Buffer.alloc(0), htlcBump)
+      .accountsPartial({
+        ...ROUTE,
+        sender: wallet.publicKey,
+        payer: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        senderTokenAccount: walletTokenAccount,
+      })
+      .rpc();
+
+    const counterpartyId = randomBytes(32);
+    const lockTxHash = randomBytes(64);
+    const link = (srcReceiver: Keypair, hash: Buffer) => program.methods
+      .linkCounterparty(Array.from(id), Array.from(counterpartyId), hash)
+      .accountsPartial({ srcReceiver: srcReceiver.publicKey, htlc })
+      .signers([srcReceiver])
+      .rpc();
+    // Only the src_receiver can link, with a lock_tx_hash of up to 64 bytes.
+    const [other] = await createUserAndAssociatedWallet();
+    await assert.rejects(link(other, lockTxHash), /NotReciever/);
+    await assert.rejects(link(bob, randomBytes(65)), /FieldTooLong/);
+    await link(bob, lockTxHash);
+    await assert.rejects(link(bob, lockTxHash));
+
+    const [counterparty] = PublicKey.findProgramAddressSync([Buffer.from("counterparty"), id], program.programId);
+    const linked = await program.account.counterparty.fetch(counterparty);
+    assert.deepEqual(linked.counterpartyId, Array.from(counterpartyId));
+    assert.deepEqual(linked.lockTxHash.slice(0, linked.lockTxHashLen), Array.from(lockTxHash));
+  });
+
+
+  it("redeems an HTLC with a quorum of guardian attestations instead of the secret", async () => {
+    const timelock = new anchor.BN((new Date().getTime() + 60000) / 1000);
+    const lockHtlc = async () => {
+      const id = randomBytes(32);
+      const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+      const [htlc, htlcBump] = PublicKey.findProgramAddressSync([id], program.programId);
+      const [htlcTokenAccount] = PublicKey.findProgramAddressSync([Buffer.from("htlc_token_account"), id], program.programId);
+      await program.methods
+        .lock(Array.from(id), Array.from(hashlock), timelock, { timestamp: {} }, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(1000), null, [], Buffer.alloc(0), htlcBump)
+        .accountsPartial({
+          ...ROUTE,
+          sender: wallet.publicKey,
+          payer: wallet.publicKey,
+          htlc,
+          htlcTokenAccount,
+          tokenContract: tokenMint,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          senderTokenAccount: walletTokenAccount,
+        })
+        .rpc();
+      return { id, htlc, htlcBump, htlcTokenAccount };
+    };
+
+    // One Ed25519 and three secp256k1 attesters, any two of them are a quorum.
+    const guardian = new Keypair();
+    const evmKeys = [0, 1, 2].map(() => secp256k1.utils.randomPrivateKey());
+    const attesters = [
+      { kind: { ed25519: {} }, key: Array.from(guardian.publicKey.toBytes()) },
+      ...evmKeys.map((evmKey) => ({
+        kind: { secp256k1: {} },
+        key: [...keccak_256(secp256k1.getPublicKey(evmKey, false).slice(1)).slice(12), ...new Array(12).fill(0)],
+      })),
+    ];
+    await program.methods.setAttesters(attesters, 2).accountsPartial({ admin: wallet.publicKey }).rpc();
+
+    // See htlc_core::attestation::message.
+    const message = (id: Buffer) => Buffer.concat([Buffer.from("HTLC destination leg completed"), program.programId.toBuffer(), id]);
+    const evmSignature = (id: Buffer, attester: number) => {
+      const signature = secp256k1.sign(keccak_256(message(id)), evmKeys[attester - 1]);
+      return { attester, signature: Array.from(signature.toCompactRawBytes()), recoveryId: signature.recovery };
+    };
+    const ed25519 = (id: Buffer) =>
+      anchor.web3.Ed25519Program.createInstructionWithPrivateKey({ privateKey: guardian.secretKey, message: message(id) });
+    const redeemAttested = (
+      { id, htlc, htlcBump, htlcTokenAccount }: { id: Buffer, htlc: PublicKey, htlcBump: number, htlcTokenAccount: PublicKey },
+      signatures: ReturnType<typeof evmSignature>[],
+      preInstructions: anchor.web3.TransactionInstruction[] = [],
+    ) => program.methods
+      .redeemAttested(Array.from(id), signatures, htlcBump)
+      .accountsPartial({
+        userSigning: wallet.publicKey,
+        htlc,
+        htlcTokenAccount,
+        sender: wallet.publicKey,
+        srcReceiver: bob.publicKey,
+        tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        srcReceiverTokenAccount: spl.getAssociatedTokenAddressSync(tokenMint, bob.publicKey),
+        senderStats: PublicKey.findProgramAddressSync(
+          [Buffer.from("sender_stats"), wallet.publicKey.toBuffer(), tokenMint.toBuffer()],
+          program.programId
+        )[0],
+        config: PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId)[0],
+        instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        payoutTokenAccount: null,
+      })
+      .preInstructions(preInstructions)
+      .rpc();
+    const isRedeemed = async (id: Buffer, htlc: PublicKey) =>
+      (await program.methods.getDetails(Array.from(id)).accountsPartial({ htlc }).view()).redeemed;
+
+    // Two of the three secp256k1 attesters, where the same attester signing
+    // twice doesn't count twice.
+    const first = await lockHtlc();
+    await assert.rejects(redeemAttested(first, [evmSignature(first.id, 1)]), /AttestationQuorumNotMet/);
+    await assert.rejects(
+      redeemAttested(first, [evmSignature(first.id, 1), evmSignature(first.id, 1)]),
+      /AttestationQuorumNotMet/
+    );
+    await redeemAttested(first, [evmSignature(first.id, 1), evmSignature(first.id, 3)]);
+    assert.equal(await isRedeemed(first.id, first.htlc), true);
+
+    // The Ed25519 attester signs through an Ed25519 program instruction, which
+    // only counts if it signs the message of this HTLC.
+    const second = await lockHtlc();
+    await assert.rejects(redeemAttested(second, [], [ed25519(second.id)]), /AttestationQuorumNotMet/);
+    await assert.rejects(
+      redeemAttested(second, [evmSignature(second.id, 2)], [ed25519(first.id)]),
+      /AttestationQuorumNotMet/
+    );
+    await redeemAttested(second, [evmSignature(second.id, 2)], [ed25519(second.id)]);
+    assert.equal(await isRedeemed(second.id, second.htlc), true);
+
+    await program.methods.setAttesters([], 0).accountsPartial({ admin: wallet.publicKey }).rpc();
+  });
+
 });
\ No newline at end of file